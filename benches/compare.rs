@@ -0,0 +1,275 @@
+//! compares WakerQueue against tokio's Notify, event-listener and futures'
+//! AtomicWaker.
+//!
+//! - `register_uncontended` and `register_contended` register a waiter and
+//!   take it back out of the queue again, from one thread and from [`THREADS`]
+//!   threads at once. AtomicWaker only holds one waker, so registering just
+//!   replaces it.
+//! - `wake_all` wakes 1, 100 and 10k waiting futures. AtomicWaker is left
+//!   out since it can't hold more than one. `wake-queue-register` waits with
+//!   [`WakerQueue::register`] instead, whose nodes are freed by the wake.
+//!
+//! run with and without the `cache-padded` feature to compare the two layouts:
+//!
+//! ```sh
+//! cargo bench --bench compare
+//! cargo bench --bench compare --features cache-padded
+//! ```
+
+// the crates compared against swap in their own loom internals under --cfg loom
+// and don't build, so neither does this.
+#[cfg(not(loom))]
+mod compare {
+    use std::{
+        future::Future,
+        hint::black_box,
+        pin::{pin, Pin},
+        sync::{Arc, Barrier},
+        task::{Context, Waker},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use criterion::{criterion_group, BenchmarkId, Criterion, Throughput};
+    use event_listener::Event;
+    use futures::task::{noop_waker, noop_waker_ref, AtomicWaker};
+    use tokio::sync::Notify;
+    use wake_queue::WakerQueue;
+
+    const LAYOUT: &str = if cfg!(feature = "cache-padded") {
+        "cache-padded"
+    } else {
+        "unpadded"
+    };
+
+    const THREADS: usize = 4;
+
+    /// polls a future once with a waker that does nothing, which registers it.
+    fn poll_noop<F: Future>(future: Pin<&mut F>) {
+        let _ = future.poll(&mut Context::from_waker(noop_waker_ref()));
+    }
+
+    /// the operation measured by the register benchmarks, for each implementation.
+    trait Register: Default + Send + Sync + 'static {
+        const NAME: &'static str;
+
+        fn register_and_cancel(&self, waker: &Waker);
+    }
+
+    impl Register for WakerQueue {
+        const NAME: &'static str = "wake-queue";
+
+        // a dropped Registration stays queued until a wake reaches it, while a
+        // dropped Notified takes itself out like the others do
+        fn register_and_cancel(&self, _: &Waker) {
+            poll_noop(pin!(self.notified()));
+        }
+    }
+
+    impl Register for Notify {
+        const NAME: &'static str = "tokio";
+
+        fn register_and_cancel(&self, _: &Waker) {
+            poll_noop(pin!(self.notified()));
+        }
+    }
+
+    impl Register for Event {
+        const NAME: &'static str = "event-listener";
+
+        fn register_and_cancel(&self, _: &Waker) {
+            poll_noop(pin!(self.listen()));
+        }
+    }
+
+    impl Register for AtomicWaker {
+        const NAME: &'static str = "atomic-waker";
+
+        fn register_and_cancel(&self, waker: &Waker) {
+            self.register(black_box(waker));
+        }
+    }
+
+    fn register_uncontended<R: Register>(c: &mut Criterion) {
+        let mut group = c.benchmark_group(format!("register_uncontended/{LAYOUT}"));
+        group.throughput(Throughput::Elements(1));
+
+        group.bench_function(R::NAME, |b| {
+            let queue = R::default();
+            let waker = noop_waker_ref();
+
+            b.iter(|| queue.register_and_cancel(waker));
+        });
+
+        group.finish();
+    }
+
+    fn register_contended<R: Register>(c: &mut Criterion) {
+        let mut group = c.benchmark_group(format!("register_contended/{LAYOUT}"));
+        group.throughput(Throughput::Elements(THREADS as u64));
+
+        group.bench_function(BenchmarkId::new(R::NAME, THREADS), |b| {
+            b.iter_custom(|iters| {
+                let queue = Arc::new(R::default());
+                let barrier = Arc::new(Barrier::new(THREADS));
+
+                let threads: Vec<_> = (0..THREADS)
+                    .map(|_| {
+                        let (queue, barrier) = (queue.clone(), barrier.clone());
+
+                        thread::spawn(move || {
+                            let waker = noop_waker_ref();
+                            barrier.wait();
+
+                            let start = Instant::now();
+
+                            for _ in 0..iters {
+                                queue.register_and_cancel(waker);
+                            }
+
+                            start.elapsed()
+                        })
+                    })
+                    .collect();
+
+                // the slowest thread decides how long the round took
+                threads
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .max()
+                    .unwrap()
+            });
+        });
+
+        group.finish();
+    }
+
+    /// times `wake` on `iters` rounds of `n` waiters set up by `register`.
+    fn time_wake_all<T>(
+        iters: u64,
+        n: usize,
+        mut register: impl FnMut(usize) -> T,
+        mut wake: impl FnMut(),
+    ) -> Duration {
+        let mut total = Duration::ZERO;
+
+        for _ in 0..iters {
+            let waiters = register(n);
+
+            let start = Instant::now();
+            wake();
+            total += start.elapsed();
+
+            drop(waiters);
+        }
+
+        total
+    }
+
+    fn wake_all(c: &mut Criterion) {
+        let mut group = c.benchmark_group(format!("wake_all/{LAYOUT}"));
+
+        for n in [1, 100, 10_000] {
+            group.throughput(Throughput::Elements(n as u64));
+
+            group.bench_with_input(BenchmarkId::new("wake-queue", n), &n, |b, &n| {
+                let queue = WakerQueue::new();
+
+                b.iter_custom(|iters| {
+                    time_wake_all(
+                        iters,
+                        n,
+                        |n| {
+                            let mut waiters: Vec<_> =
+                                (0..n).map(|_| Box::pin(queue.notified())).collect();
+                            waiters
+                                .iter_mut()
+                                .for_each(|waiter| poll_noop(waiter.as_mut()));
+                            waiters
+                        },
+                        || queue.wake_all(),
+                    )
+                });
+            });
+
+            group.bench_with_input(BenchmarkId::new("wake-queue-register", n), &n, |b, &n| {
+                let queue = WakerQueue::new();
+
+                b.iter_custom(|iters| {
+                    time_wake_all(
+                        iters,
+                        n,
+                        |n| {
+                            for _ in 0..n {
+                                queue.register(noop_waker());
+                            }
+                        },
+                        || queue.wake_all(),
+                    )
+                });
+            });
+
+            group.bench_with_input(BenchmarkId::new("tokio", n), &n, |b, &n| {
+                let notify = Notify::new();
+
+                b.iter_custom(|iters| {
+                    time_wake_all(
+                        iters,
+                        n,
+                        |n| {
+                            let mut waiters: Vec<_> =
+                                (0..n).map(|_| Box::pin(notify.notified())).collect();
+                            waiters
+                                .iter_mut()
+                                .for_each(|waiter| poll_noop(waiter.as_mut()));
+                            waiters
+                        },
+                        || notify.notify_waiters(),
+                    )
+                });
+            });
+
+            group.bench_with_input(BenchmarkId::new("event-listener", n), &n, |b, &n| {
+                let event = Event::new();
+
+                b.iter_custom(|iters| {
+                    time_wake_all(
+                        iters,
+                        n,
+                        |n| {
+                            let mut waiters: Vec<_> = (0..n).map(|_| event.listen()).collect();
+                            waiters
+                                .iter_mut()
+                                .for_each(|waiter| poll_noop(Pin::new(waiter)));
+                            waiters
+                        },
+                        || {
+                            event.notify(usize::MAX);
+                        },
+                    )
+                });
+            });
+        }
+
+        group.finish();
+    }
+
+    criterion_group!(
+        benches,
+        register_uncontended::<WakerQueue>,
+        register_uncontended::<Notify>,
+        register_uncontended::<Event>,
+        register_uncontended::<AtomicWaker>,
+        register_contended::<WakerQueue>,
+        register_contended::<Notify>,
+        register_contended::<Event>,
+        register_contended::<AtomicWaker>,
+        wake_all,
+    );
+}
+
+#[cfg(not(loom))]
+criterion::criterion_main!(compare::benches);
+
+#[cfg(loom)]
+fn main() {}
@@ -0,0 +1,108 @@
+//! measures the cost of registering and waking wakers.
+//!
+//! run with and without the `node-pool` feature to compare recycled nodes
+//! against a fresh allocation per register:
+//!
+//! ```sh
+//! cargo bench --bench register
+//! cargo bench --bench register --features node-pool
+//! ```
+
+use std::{
+    ptr,
+    sync::{Arc, Barrier},
+    task::{RawWaker, RawWakerVTable, Waker},
+    thread,
+};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wake_queue::WakerQueue;
+
+const POOL: &str = if cfg!(feature = "node-pool") {
+    "node-pool"
+} else {
+    "alloc"
+};
+
+/// a waker that does nothing, without the refcounting an Arc backed one adds to
+/// every clone.
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+
+    fn noop(_: *const ()) {}
+
+    // safety: the vtable does nothing with the data pointer
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+fn register_wake_all(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("register_wake_all/{POOL}"));
+
+    for n in [1, 100, 1000] {
+        group.throughput(Throughput::Elements(n));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            let queue = WakerQueue::new();
+            let waker = noop_waker();
+
+            b.iter(|| {
+                for _ in 0..n {
+                    queue.register(black_box(waker.clone()));
+                }
+
+                queue.wake_all();
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn register_wake_one_across_threads(c: &mut Criterion) {
+    const N: u64 = 1000;
+
+    let mut group = c.benchmark_group(format!("register_wake_one_across_threads/{POOL}"));
+    group.throughput(Throughput::Elements(N));
+
+    group.bench_function(BenchmarkId::from_parameter(N), |b| {
+        b.iter_custom(|iters| {
+            let queue = Arc::new(WakerQueue::new());
+            let barrier = Arc::new(Barrier::new(2));
+
+            let waker_thread = {
+                let queue = queue.clone();
+                let barrier = barrier.clone();
+
+                thread::spawn(move || {
+                    barrier.wait();
+
+                    let mut woken = 0;
+
+                    while woken < iters * N {
+                        if queue.wake_one() {
+                            woken += 1;
+                        }
+                    }
+                })
+            };
+
+            barrier.wait();
+            let start = std::time::Instant::now();
+
+            for _ in 0..iters * N {
+                queue.register(noop_waker());
+            }
+
+            waker_thread.join().unwrap();
+            start.elapsed()
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, register_wake_all, register_wake_one_across_threads);
+criterion_main!(benches);
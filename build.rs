@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(futex)");
+    println!("cargo::rerun-if-changed=build.rs");
+
+    let has = |var: &str| std::env::var_os(var).is_some();
+
+    // blocking waits sleep on a futex on linux. loom and shuttle can't model
+    // one, so they keep the park based fallback.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux")
+        && has("CARGO_FEATURE_STD")
+        && !has("CARGO_CFG_LOOM")
+        && !has("CARGO_CFG_SHUTTLE")
+    {
+        println!("cargo::rustc-cfg=futex");
+    }
+}
@@ -0,0 +1,151 @@
+use core::{cell::UnsafeCell, task::Waker};
+
+use crate::{
+    sync::{const_fn, fence, AtomicU8, Ordering},
+    RegisterError,
+};
+
+const EMPTY: u8 = 0;
+/// a register is writing a waker into the slot.
+const WRITING: u8 = 1;
+const FULL: u8 = 2;
+/// a wake is taking the waker out of the slot.
+const TAKING: u8 = 3;
+
+struct Slot {
+    state: AtomicU8,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl Slot {
+    const_fn! {
+        fn new() -> Self {
+            Slot {
+                state: AtomicU8::new(EMPTY),
+                waker: UnsafeCell::new(None),
+            }
+        }
+    }
+
+    /// takes the waker out of a full slot, returning None if it wasn't full.
+    fn take(&self) -> Option<Waker> {
+        self.state
+            .compare_exchange(FULL, TAKING, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        // safety: TAKING gives us sole access to the waker
+        let waker = unsafe { (*self.waker.get()).take() };
+        self.state.store(EMPTY, Ordering::Release);
+
+        waker
+    }
+}
+
+/// a WakerQueue that stores up to `N` wakers inline, without allocating.
+///
+/// it has the same `register`/`wake_all` surface as [`WakerQueue`](crate::WakerQueue)
+/// and can be created in a `static`. wakers are kept in slots rather than a list,
+/// so they aren't woken in the order they were registered in.
+pub struct ArrayWakerQueue<const N: usize> {
+    slots: [Slot; N],
+}
+
+// safety: a slot's waker is only touched by whoever moved its state
+// to WRITING or TAKING.
+unsafe impl<const N: usize> Sync for ArrayWakerQueue<N> {}
+
+impl<const N: usize> Default for ArrayWakerQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ArrayWakerQueue<N> {
+    const_fn! {
+        /// creates a new ArrayWakerQueue.
+        pub fn new() -> Self {
+            ArrayWakerQueue {
+                #[cfg(not(loom))]
+                slots: [const { Slot::new() }; N],
+                #[cfg(loom)]
+                slots: core::array::from_fn(|_| Slot::new()),
+            }
+        }
+    }
+
+    /// returns the number of wakers the ArrayWakerQueue can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// stores a waker in the ArrayWakerQueue.
+    ///
+    /// if every slot is taken the waker is woken right away instead.
+    ///
+    /// this is thread safe.
+    pub fn register(&self, waker: Waker) {
+        if let Err(err) = self.try_register(waker) {
+            err.into_waker().wake();
+        }
+    }
+
+    /// stores a waker in the ArrayWakerQueue, or hands it back with
+    /// [`RegisterError::Full`] if every slot is taken.
+    ///
+    /// this is thread safe.
+    pub fn try_register(&self, waker: Waker) -> Result<(), RegisterError> {
+        for slot in &self.slots {
+            if slot
+                .state
+                .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                // safety: WRITING gives us sole access to the waker
+                unsafe { *slot.waker.get() = Some(waker) };
+                slot.state.store(FULL, Ordering::Release);
+
+                // pairs with the fence in wake_one and wake_all, the same way as the
+                // one in WakerQueue::push. either they see FULL or the caller sees
+                // whatever they stored before waking.
+                fence(Ordering::SeqCst);
+
+                return Ok(());
+            }
+        }
+
+        Err(RegisterError::Full(waker))
+    }
+
+    /// wakes one waker in the ArrayWakerQueue and removes it.
+    ///
+    /// returns true if a waker was woken.
+    ///
+    /// this is thread safe.
+    pub fn wake_one(&self) -> bool {
+        // pairs with the fence in try_register
+        fence(Ordering::SeqCst);
+
+        for slot in &self.slots {
+            if let Some(w) = slot.take() {
+                w.wake();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// wakes all wakers in the ArrayWakerQueue and clears it.
+    ///
+    /// this is thread safe.
+    pub fn wake_all(&self) {
+        // pairs with the fence in try_register
+        fence(Ordering::SeqCst);
+
+        for slot in &self.slots {
+            if let Some(w) = slot.take() {
+                w.wake();
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! strategies for waiting on another thread inside the queue.
+//!
+//! a few operations can't finish until a racing one does: `wake_one`, `wake_n`
+//! and dropping a registration have to wait for a register that swapped the tail
+//! to link it to the rest of the queue, and for whoever holds the head token to
+//! give it back. those waits are normally short, but if the other thread is
+//! preempted in between they last until it's scheduled again, which on a machine
+//! with few cores can be a whole timeslice. `wake_all` never waits, it leaves what
+//! it can't finish to the other thread.
+//!
+//! a [`Backoff`] decides what to do in the meantime. it's picked per queue with
+//! [`WakerQueue::with_backoff`](crate::WakerQueue::with_backoff), and
+//! [`SpinThenYield`] is used by default.
+
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+use crate::sync;
+
+/// decides how to wait for another thread to finish its part of an operation.
+///
+/// implementations must not call back into the queue.
+pub trait Backoff: Sync {
+    /// waits a little before the condition is checked again.
+    ///
+    /// `step` is 0 the first time this is called for a wait and goes up by one
+    /// every time after that, so the wait can grow the longer it takes.
+    fn snooze(&self, step: u32);
+}
+
+/// spins with a cpu hint, never giving up the timeslice.
+///
+/// the lowest latency if the other thread is running, and the worst choice if it
+/// isn't, since the whole timeslice is spent waiting for it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Spin;
+
+impl Backoff for Spin {
+    fn snooze(&self, _step: u32) {
+        sync::spin_loop();
+    }
+}
+
+/// spins for exponentially longer on every step, then yields the timeslice
+/// once that's taken a while.
+///
+/// this is the default, and does well unless the machine is oversubscribed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinThenYield;
+
+impl SpinThenYield {
+    /// number of steps spent spinning before yielding, which is 2^SPIN_LIMIT - 1
+    /// spins in total.
+    const SPIN_LIMIT: u32 = 6;
+}
+
+impl Backoff for SpinThenYield {
+    fn snooze(&self, step: u32) {
+        if step < Self::SPIN_LIMIT {
+            for _ in 0..1 << step {
+                sync::spin_loop();
+            }
+        } else {
+            sync::yield_now();
+        }
+    }
+}
+
+/// yields the timeslice on every step.
+///
+/// without std there's nothing to yield to, so this spins instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Yield;
+
+impl Backoff for Yield {
+    fn snooze(&self, _step: u32) {
+        sync::yield_now();
+    }
+}
+
+/// sleeps for exponentially longer on every step, up to
+/// [`MAX_TIMEOUT`](Self::MAX_TIMEOUT).
+///
+/// nothing wakes it early, so this trades latency for not taking cpu time away
+/// from the thread being waited on. suited to vms with few vcpus.
+///
+/// it sleeps rather than parks, so it can't use up an unpark meant for something
+/// else on the same thread, like an executor waiting for a wake.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Park;
+
+#[cfg(feature = "std")]
+impl Park {
+    /// the timeout of the first step.
+    pub const MIN_TIMEOUT: Duration = Duration::from_micros(1);
+    /// the longest a single step sleeps for.
+    pub const MAX_TIMEOUT: Duration = Duration::from_millis(1);
+}
+
+#[cfg(feature = "std")]
+impl Backoff for Park {
+    fn snooze(&self, step: u32) {
+        let timeout = Self::MIN_TIMEOUT
+            .saturating_mul(1 << step.min(16))
+            .min(Self::MAX_TIMEOUT);
+
+        sync::sleep(timeout);
+    }
+}
@@ -0,0 +1,124 @@
+//! waiting on a WakerQueue from plain threads.
+//!
+//! on linux a blocked thread sleeps on a futex word in its own node, so waiting
+//! needs neither a waker nor a handle to the thread. elsewhere it parks and is
+//! unparked by a waker.
+
+#[cfg(not(futex))]
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Wake, Waker},
+    thread::{self, Thread},
+};
+use std::{
+    pin::pin,
+    time::{Duration, Instant},
+};
+
+use crate::WakerQueue;
+#[cfg(futex)]
+use crate::{futex, sync::Ordering};
+
+/// a waker that unparks the thread that created it.
+#[cfg(not(futex))]
+struct Unpark(Thread);
+
+#[cfg(not(futex))]
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl WakerQueue {
+    /// blocks the current thread until the queue is woken.
+    ///
+    /// the thread waits the same way a [`notified`](Self::notified) future
+    /// would, so blocked threads and async tasks can wait on the same WakerQueue.
+    /// this returns right away on a closed WakerQueue or if a permit was stored by
+    /// [`notify_one`](Self::notify_one).
+    ///
+    /// this is thread safe.
+    pub fn wait_blocking(&self) {
+        self.wait_blocking_until(None);
+    }
+
+    /// blocks the current thread until the queue is woken or `timeout` has passed.
+    ///
+    /// returns false if the timeout passed first, in which case the waiter has been
+    /// taken back out of the queue. see [`wait_blocking`](Self::wait_blocking).
+    ///
+    /// this is thread safe.
+    pub fn wait_blocking_timeout(&self, timeout: Duration) -> bool {
+        // a deadline too far out to represent is as good as none
+        self.wait_blocking_until(Instant::now().checked_add(timeout))
+    }
+
+    #[cfg(futex)]
+    fn wait_blocking_until(&self, deadline: Option<Instant>) -> bool {
+        let mut notified = pin!(self.notified());
+
+        if notified.as_mut().register_futex() {
+            return true;
+        }
+
+        // the word is only ever set once, but the wait can return without it
+        loop {
+            if notified.futex_word().load(Ordering::Acquire) != 0 {
+                return true;
+            }
+
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        // a wake that got to the waiter before it was taken back
+                        // out still counts, even if the word isn't set yet
+                        return notified.as_mut().cancel();
+                    }
+
+                    Some(deadline - now)
+                }
+            };
+
+            futex::wait(notified.futex_word(), 0, timeout);
+        }
+    }
+
+    #[cfg(not(futex))]
+    fn wait_blocking_until(&self, deadline: Option<Instant>) -> bool {
+        let mut notified = pin!(self.notified());
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if notified.as_mut().poll(&mut cx).is_ready() {
+                return true;
+            }
+
+            // parking can return without an unpark, so the future is polled again
+            // on every loop to tell a wake from a spurious return
+            match deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        // a wake that got to the waiter before it was taken back
+                        // out still counts
+                        return notified.as_mut().cancel();
+                    }
+
+                    thread::park_timeout(deadline - now);
+                }
+            }
+        }
+    }
+}
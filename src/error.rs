@@ -0,0 +1,53 @@
+use core::{error::Error, fmt, task::Waker};
+
+/// error returned by [`WakerQueue::try_register`](crate::WakerQueue::try_register).
+///
+/// the waker is handed back so the caller can wake or reuse it.
+#[derive(Debug)]
+pub enum RegisterError {
+    /// the WakerQueue has been closed.
+    Closed(Waker),
+    /// the WakerQueue is at capacity.
+    Full(Waker),
+    /// memory for the waker node couldn't be allocated.
+    AllocationFailed(Waker),
+}
+
+impl RegisterError {
+    /// returns the waker that failed to register.
+    pub fn into_waker(self) -> Waker {
+        match self {
+            RegisterError::Closed(waker)
+            | RegisterError::Full(waker)
+            | RegisterError::AllocationFailed(waker) => waker,
+        }
+    }
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::Closed(_) => "WakerQueue is closed".fmt(f),
+            RegisterError::Full(_) => "WakerQueue is full".fmt(f),
+            RegisterError::AllocationFailed(_) => "failed to allocate waker node".fmt(f),
+        }
+    }
+}
+
+impl Error for RegisterError {}
+
+/// error returned by [`NotifiedTimeout`](crate::NotifiedTimeout) if its deadline
+/// passed before the queue was woken.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(pub(crate) ());
+
+#[cfg(feature = "std")]
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "deadline has elapsed".fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Elapsed {}
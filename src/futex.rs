@@ -0,0 +1,51 @@
+//! the futex calls behind blocking waits on linux.
+
+use core::{ptr, time::Duration};
+
+use crate::sync::AtomicU32;
+
+/// blocks the thread while `word` is `expected`, for at most `timeout`.
+///
+/// this can return early for no reason, the caller has to check the word again.
+pub(crate) fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    // a timeout too long for a timespec is as good as none
+    let timespec = timeout.and_then(|timeout| {
+        Some(libc::timespec {
+            tv_sec: timeout.as_secs().try_into().ok()?,
+            // always below a second, so it fits
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        })
+    });
+
+    let timespec_ptr = timespec.as_ref().map_or(ptr::null(), ptr::from_ref);
+
+    // safety: the word is a valid u32 for the whole call. the result is ignored
+    // since timeouts, signals and a word that already changed all look the same to
+    // the caller, which checks the word either way.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            timespec_ptr,
+        )
+    };
+}
+
+/// wakes a thread blocked in [`wait`] on `word`.
+///
+/// `word` doesn't have to point to anything anymore, the kernel only uses the
+/// address to find who's waiting on it. if the memory was reused for another futex,
+/// its waiter wakes up spuriously, which every futex waiter has to handle anyway.
+pub(crate) fn wake(word: *const AtomicU32) {
+    // safety: FUTEX_WAKE never reads or writes through the pointer
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word,
+            libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+            1,
+        )
+    };
+}
@@ -1,22 +1,116 @@
-use std::{
-    ptr::null_mut,
-    sync::atomic::{AtomicPtr, Ordering},
-    task::Waker,
-};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#[cfg(feature = "cache-padded")]
-use crossbeam_utils::CachePadded;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::ptr::null_mut;
+#[cfg(feature = "alloc")]
+use core::{marker::PhantomData, task::Waker};
 
 #[cfg(feature = "cache-padded")]
-pub struct WakerQueue {
-    head: CachePadded<AtomicPtr<WakerNode>>,
-    tail: CachePadded<AtomicPtr<WakerNode>>,
+use crossbeam_utils::CachePadded as Padded;
+
+#[cfg(all(any(loom, shuttle), feature = "node-pool"))]
+compile_error!(
+    "the node-pool feature keeps nodes in statics and can't be used with loom or shuttle"
+);
+
+mod array;
+pub mod backoff;
+#[cfg(feature = "std")]
+mod blocking;
+mod error;
+#[cfg(futex)]
+mod futex;
+mod node;
+mod notified;
+#[cfg(feature = "node-pool")]
+pub mod pool;
+#[cfg(feature = "alloc")]
+mod registration;
+mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+mod timeout;
+
+pub use array::ArrayWakerQueue;
+pub use backoff::Backoff;
+#[cfg(feature = "std")]
+pub use error::Elapsed;
+pub use error::RegisterError;
+pub use notified::Notified;
+#[cfg(feature = "alloc")]
+pub use registration::Registration;
+#[cfg(feature = "std")]
+pub use timeout::NotifiedTimeout;
+
+use node::{Claim, WakerNode, DEFERRED, END};
+use sync::{const_fn, fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// stands in for CachePadded when the cache-padded feature is off.
+#[cfg(not(feature = "cache-padded"))]
+struct Padded<T>(T);
+
+#[cfg(not(feature = "cache-padded"))]
+impl<T> Padded<T> {
+    const fn new(value: T) -> Self {
+        Padded(value)
+    }
 }
 
 #[cfg(not(feature = "cache-padded"))]
+impl<T> core::ops::Deref for Padded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// nodes are reclaimed without epochs or hazard pointers, since nothing ever
+// dereferences a node it doesn't hold a reference to:
+//
+// - a node is in the queue from the tail swap in push until it's popped, and the
+//   queue holds one reference to it for that whole time. registrations and inline
+//   futures hold their own, see WakerNode::refs.
+// - only the holder of the head token (see acquire_head) pops or unlinks nodes,
+//   and wake_all detaches the whole list before walking it, so nodes are never
+//   read through a pointer someone else might free.
+// - the one pointer held without a reference is the previous tail in push, which
+//   is written to after the swap. nothing can pop that node while its next is null
+//   and tail has moved on, see unlink_head and unlink, which wait for the link
+//   instead. wake_all doesn't wait, it hands the node over to the register by
+//   marking it DEFERRED, see wake_detached.
+//
+// a node is only freed (or put back in the pool) once the last reference is let go.
 pub struct WakerQueue {
-    head: AtomicPtr<WakerNode>,
-    tail: AtomicPtr<WakerNode>,
+    head: Padded<AtomicPtr<WakerNode>>,
+    tail: Padded<AtomicPtr<WakerNode>>,
+    generation: Padded<AtomicUsize>,
+    /// generation as of the last wake_all that was carried out, only written by
+    /// whoever holds the head token. a wake_all that can't get the token leaves it
+    /// behind the generation, see release_head.
+    woken: Padded<AtomicUsize>,
+    permit: Padded<AtomicBool>,
+    closed: Padded<AtomicBool>,
+    /// number of nodes in the queue, only tracked for bounded queues.
+    len: Padded<AtomicUsize>,
+    capacity: usize,
+    overflow: Overflow,
+    backoff: &'static dyn Backoff,
+}
+
+/// what a bounded WakerQueue does with a waker registered while it's full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// refuses the new waker. [`WakerQueue::register`] wakes it right away and
+    /// [`WakerQueue::try_register`] returns [`RegisterError::Full`].
+    Reject,
+    /// wakes and drops the oldest waker in the queue to make room for the new one.
+    WakeOldest,
+    /// wakes the new waker right away instead of queuing it.
+    WakeNew,
 }
 
 impl Drop for WakerQueue {
@@ -29,22 +123,17 @@ impl Drop for WakerQueue {
                 unreachable!("failed to deallocate WakerQueue");
             }
 
-            let tmp = unsafe { Box::from_raw(head) };
-            head = tmp.next.swap(null_mut(), Ordering::SeqCst);
-            drop(tmp);
+            let tmp = head;
+            head = unsafe { (*tmp).next.swap(null_mut(), Ordering::SeqCst) };
+            unsafe { WakerNode::release(tmp) };
         }
 
         if !head.is_null() {
-            unsafe { drop(Box::from_raw(head)) };
+            unsafe { WakerNode::release(head) };
         }
     }
 }
 
-struct WakerNode {
-    next: AtomicPtr<WakerNode>,
-    waker: Option<Waker>,
-}
-
 impl Default for WakerQueue {
     fn default() -> Self {
         Self::new()
@@ -52,102 +141,699 @@ impl Default for WakerQueue {
 }
 
 impl WakerQueue {
-    #[cfg(feature = "cache-padded")]
-    /// creates a new cache padded WakerQueue.
-    pub const fn new() -> Self {
-        WakerQueue {
-            head: CachePadded::new(AtomicPtr::new(null_mut::<WakerNode>())),
-            tail: CachePadded::new(AtomicPtr::new(null_mut::<WakerNode>())),
+    const_fn! {
+        /// creates a new unbounded WakerQueue.
+        ///
+        /// with the cache-padded feature every atomic in the queue sits on its own cache line.
+        pub fn new() -> Self {
+            Self::with_capacity(usize::MAX, Overflow::Reject)
+        }
+    }
+
+    const_fn! {
+        /// creates a new WakerQueue that holds at most `capacity` wakers.
+        ///
+        /// registering a waker while the queue is full is handled according to `overflow`.
+        /// cancelled registrations that haven't been reached by a wake yet still take up
+        /// room. a `capacity` of `usize::MAX` means unbounded.
+        pub fn with_capacity(capacity: usize, overflow: Overflow) -> Self {
+            WakerQueue {
+                head: Padded::new(AtomicPtr::new(null_mut::<WakerNode>())),
+                tail: Padded::new(AtomicPtr::new(null_mut::<WakerNode>())),
+                generation: Padded::new(AtomicUsize::new(0)),
+                woken: Padded::new(AtomicUsize::new(0)),
+                permit: Padded::new(AtomicBool::new(false)),
+                closed: Padded::new(AtomicBool::new(false)),
+                len: Padded::new(AtomicUsize::new(0)),
+                capacity,
+                overflow,
+                backoff: &backoff::SpinThenYield,
+            }
         }
     }
 
-    #[cfg(not(feature = "cache-padded"))]
-    /// creates a new WakerQueue.
-    pub const fn new() -> Self {
-        WakerQueue {
-            head: AtomicPtr::new(null_mut::<WakerNode>()),
-            tail: AtomicPtr::new(null_mut::<WakerNode>()),
+    const_fn! {
+        /// sets how the WakerQueue waits for racing registers and wakes to finish.
+        ///
+        /// see [`backoff`] for the available strategies. the default is
+        /// [`SpinThenYield`](backoff::SpinThenYield).
+        pub fn with_backoff(mut self, backoff: &'static dyn Backoff) -> Self {
+            self.backoff = backoff;
+            self
         }
     }
 
     /// appends a waker to the WakerQueue.
     ///
+    /// if a permit was stored by [`notify_one`](Self::notify_one) the oldest waker
+    /// in the queue (usually this one) is woken right away.
+    ///
+    /// if the WakerQueue is closed, or full and not allowed to make room for it,
+    /// the waker is woken right away instead.
+    ///
     /// this is thread safe.
+    #[cfg(feature = "alloc")]
     pub fn register(&self, waker: Waker) {
-        let node = Box::into_raw(Box::new(WakerNode {
-            next: AtomicPtr::new(null_mut()),
-            waker: Some(waker),
-        }));
+        if self.is_closed() || !self.reserve() {
+            waker.wake();
+            return;
+        }
 
-        let prev_tail = self.tail.swap(node, Ordering::Release);
+        self.push(WakerNode::alloc(waker, 1).as_ptr());
+    }
+
+    /// appends a waker to the WakerQueue and returns a handle to it.
+    ///
+    /// dropping the handle removes the waker from the queue, so a future that
+    /// is cancelled before being woken doesn't leave its waker behind.
+    ///
+    /// if the WakerQueue is closed, or full and not allowed to make room for it,
+    /// the registration is notified right away.
+    ///
+    /// this is thread safe.
+    #[cfg(feature = "alloc")]
+    pub fn register_handle(&self, waker: Waker) -> Registration<'_> {
+        if !self.reserve() {
+            return Registration::notified(WakerNode::alloc(waker, 1));
+        }
+
+        let node = WakerNode::alloc(waker, 2);
+        self.push(node.as_ptr());
+
+        Registration {
+            node,
+            _queue: PhantomData,
+        }
+    }
+
+    /// appends a waker to the WakerQueue and returns a handle to it, or hands
+    /// the waker back if it can't be registered.
+    ///
+    /// unlike [`register_handle`](Self::register_handle) this never wakes the
+    /// waker on a closed WakerQueue and never aborts if allocation fails. a full
+    /// WakerQueue only hands the waker back with [`Overflow::Reject`], or with
+    /// [`Overflow::WakeOldest`] if there was nothing to make room with.
+    /// a registration racing with [`close`](Self::close) may still succeed,
+    /// in which case it is notified right away.
+    ///
+    /// this is thread safe.
+    #[cfg(feature = "alloc")]
+    pub fn try_register(&self, waker: Waker) -> Result<Registration<'_>, RegisterError> {
+        if self.is_closed() {
+            return Err(RegisterError::Closed(waker));
+        }
+
+        if !self.reserve() {
+            return match self.overflow {
+                Overflow::WakeNew => match WakerNode::try_alloc(waker, 1) {
+                    Ok(node) => Ok(Registration::notified(node)),
+                    Err(waker) => Err(RegisterError::AllocationFailed(waker)),
+                },
+                _ => Err(RegisterError::Full(waker)),
+            };
+        }
+
+        let node = match WakerNode::try_alloc(waker, 2) {
+            Ok(node) => node,
+            Err(waker) => {
+                self.unreserve(1);
+                return Err(RegisterError::AllocationFailed(waker));
+            }
+        };
+
+        self.push(node.as_ptr());
+
+        Ok(Registration {
+            node,
+            _queue: PhantomData,
+        })
+    }
+
+    /// returns the current generation of the WakerQueue.
+    ///
+    /// the generation goes up by one on every [`wake_all`](Self::wake_all), so a
+    /// waiter can observe it before checking its condition and pass it to
+    /// [`register_if_generation`](Self::register_if_generation) afterwards.
+    /// it wraps around on overflow.
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// appends a waker to the WakerQueue only if no `wake_all` has happened
+    /// since `generation` was observed.
+    ///
+    /// returns false if the generation has moved on, in which case the caller
+    /// should re-check its condition instead of waiting. the waker may still get
+    /// woken if it raced with the `wake_all`.
+    ///
+    /// this is thread safe.
+    #[cfg(feature = "alloc")]
+    pub fn register_if_generation(&self, generation: usize, waker: Waker) -> bool {
+        let registration = self.register_handle(waker);
+
+        // the waker has to be in the queue before the generation is checked,
+        // otherwise a wake_all could slip in between the two. dropping the
+        // registration cancels it.
+        if self.generation() != generation {
+            return false;
+        }
+
+        registration.detach();
+        true
+    }
+
+    /// returns a future that completes once the queue is woken.
+    ///
+    /// the future carries its own waker node, so waiting on it never allocates.
+    ///
+    /// a `wake_all` that happens after this is called but before the future
+    /// is first polled still completes it, as does a permit stored by
+    /// [`notify_one`](Self::notify_one). on a closed WakerQueue the future
+    /// completes immediately.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified::new(self)
+    }
+
+    fn push(&self, node: *mut WakerNode) {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let prev_tail = self.tail.swap(node, Ordering::SeqCst);
 
         unsafe {
-            match prev_tail.as_mut() {
-                Some(prev) => prev.next.store(node, Ordering::Relaxed),
-                // generally, if tail is null it's implied that head is also null.
-                // however, this might not be true if wake_all is happening simultaneously
-                // so we need a loop here to fix the race condition.
-                //
-                // in theory, this is a rare occurence and should not impact performance
-                None => loop {
-                    if self
-                        .head
-                        .compare_exchange_weak(
-                            null_mut(),
-                            node,
-                            Ordering::Relaxed,
-                            Ordering::Relaxed,
-                        )
-                        .is_ok()
-                    {
-                        break;
+            match prev_tail.as_ref() {
+                Some(prev) => {
+                    // a wake_all that got to prev before we linked it left prev and
+                    // the rest of the list to us
+                    if prev.next.swap(node, Ordering::AcqRel) == DEFERRED {
+                        WakerNode::release(prev_tail);
+                        self.wake_detached(node);
                     }
-                },
+                }
+                // tail being null means the queue was empty, and whoever emptied it
+                // left head null on the way out. setting it hands out the head token.
+                None => {
+                    // every node that was queued when an earlier wake_all was called
+                    // is gone, so there's nothing left for it to wake
+                    self.woken.store(generation, Ordering::Relaxed);
+                    self.release_head(node);
+                }
+            }
+        }
+
+        // pairs with the fence in acquire_head and notify_one, which come between
+        // close, notify_one and wake_all storing their flag or generation and
+        // checking tail. either they see our node or we see what they stored,
+        // and if they missed us we have to wake ourselves. the same goes for the
+        // generation check in Notified.
+        fence(Ordering::SeqCst);
+
+        if self.closed.load(Ordering::SeqCst) {
+            self.wake_all();
+            return;
+        }
+
+        if self.permit.load(Ordering::SeqCst) && self.take_permit() {
+            self.notify_one();
+        }
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.capacity != usize::MAX
+    }
+
+    /// reserves room for a new node, making room according to the overflow
+    /// policy if the queue is full.
+    ///
+    /// returns false if the new waker shouldn't be queued.
+    fn reserve(&self) -> bool {
+        if !self.is_bounded() {
+            return true;
+        }
+
+        loop {
+            if self
+                .len
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                    (len < self.capacity).then_some(len + 1)
+                })
+                .is_ok()
+            {
+                return true;
+            }
+
+            // if there's nothing to evict the queue is full of registers
+            // that haven't finished yet, so treat it like any other refusal
+            if self.overflow != Overflow::WakeOldest || self.wake_front(1, false) == 0 {
+                return false;
+            }
+        }
+    }
+
+    /// gives back room taken by `n` nodes.
+    fn unreserve(&self, n: usize) {
+        if self.is_bounded() {
+            self.len.fetch_sub(n, Ordering::AcqRel);
+        }
+    }
+
+    /// consumes the stored permit, returning true if there was one.
+    fn take_permit(&self) -> bool {
+        self.permit.swap(false, Ordering::SeqCst)
+    }
+
+    /// takes ownership of the front of the queue.
+    ///
+    /// swapping head out for null acts as a token: whoever holds a non-null head
+    /// is the only one allowed to pop from the queue until it's given back
+    /// (by release_head) or the queue is emptied (by swapping tail to null).
+    fn acquire_head(&self) -> Option<*mut WakerNode> {
+        // pairs with the fence in push
+        fence(Ordering::SeqCst);
+
+        let mut step = 0;
+
+        loop {
+            // tail being null implies nothing has been pushed into the queue
+            if self.tail.load(Ordering::SeqCst).is_null() {
+                return None;
+            }
+
+            // only try to take it once it looks like it's there, so waiting
+            // doesn't keep pulling the cache line away from its owner
+            if !self.head.load(Ordering::Relaxed).is_null() {
+                let head = self.head.swap(null_mut(), Ordering::Acquire);
+
+                if !head.is_null() {
+                    return Some(head);
+                }
+            }
+
+            // if tail isn't null we are either waiting for a register to
+            // finish setting the head or for another waker to give it back
+            self.backoff.snooze(step);
+            step = step.saturating_add(1);
+        }
+    }
+
+    /// takes the head token if it's there, without waiting for it.
+    fn try_acquire_head(&self) -> Option<*mut WakerNode> {
+        if self.tail.load(Ordering::SeqCst).is_null() || self.head.load(Ordering::Relaxed).is_null()
+        {
+            return None;
+        }
+
+        let head = self.head.swap(null_mut(), Ordering::Acquire);
+        (!head.is_null()).then_some(head)
+    }
+
+    /// gives the head token back, with `head` as the front of the queue.
+    ///
+    /// this is a swap rather than a store even though head is known to be null.
+    /// loom doesn't order plain stores against the swap in acquire_head the way
+    /// hardware does, and would otherwise let a waiting thread take a stale null.
+    ///
+    /// if a wake_all came in while the token was held, it's carried out here.
+    fn release_head(&self, head: *mut WakerNode) {
+        self.head.swap(head, Ordering::Release);
+
+        // pairs with the fence in wake_all. either it sees the token we just gave
+        // back, or we see the generation it bumped. if someone else takes the token
+        // first, they see it when giving it back.
+        fence(Ordering::SeqCst);
+
+        if self.generation.load(Ordering::SeqCst) != self.woken.load(Ordering::Relaxed) {
+            if let Some(head) = self.try_acquire_head() {
+                unsafe { self.wake_all_from(head) };
             }
         }
     }
 
+    /// unlinks head from the front of the queue.
+    ///
+    /// returns the next head. if it is null the queue has been emptied and the
+    /// head token was released with it, otherwise the caller still holds the token
+    /// for the returned head. either way the caller now owns the queue's reference
+    /// to the unlinked node.
+    ///
+    /// # Safety
+    ///
+    /// the caller must hold the head token for `head` (see `acquire_head`).
+    unsafe fn unlink_head(&self, head: *mut WakerNode) -> *mut WakerNode {
+        // safety: holding head means nobody else can free it
+        let next = unsafe { (*head).next.load(Ordering::Acquire) };
+
+        if !next.is_null() {
+            return next;
+        }
+
+        // head is the only node, so try to empty the queue. the head token
+        // is released along with it since head stays null.
+        if self
+            .tail
+            .compare_exchange(head, null_mut(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            return null_mut();
+        }
+
+        // a register swapped the tail but hasn't linked it to head yet
+        unsafe { WakerNode::wait_next(head, self.backoff) }
+    }
+
+    /// takes an inline node out of the queue, or waits for whoever popped it to
+    /// let go of it.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be an inline node pushed onto this queue that has been
+    /// cancelled or notified.
+    unsafe fn remove(&self, node: *mut WakerNode) {
+        let node_ref = unsafe { &*node };
+
+        // nodes only get notified once they've been popped, so those don't have
+        // to be looked for. neither do nodes that have already been let go of.
+        if !node_ref.is_notified() && node_ref.refs.load(Ordering::Acquire) != 0 {
+            if let Some(head) = self.acquire_head() {
+                unsafe { self.unlink(head, node) };
+            }
+        }
+
+        // whoever popped it lets go right after taking the waker out,
+        // but might have been preempted in between
+        let mut step = 0;
+
+        while node_ref.refs.load(Ordering::Acquire) != 0 {
+            self.backoff.snooze(step);
+            step = step.saturating_add(1);
+        }
+    }
+
+    /// unlinks `node` if it's still in the queue and gives the head token back.
+    ///
+    /// # Safety
+    ///
+    /// the caller must hold the head token for `head` and `node` must be a node
+    /// that was pushed onto this queue.
+    unsafe fn unlink(&self, head: *mut WakerNode, node: *mut WakerNode) {
+        if head == node {
+            let next = unsafe { self.unlink_head(head) };
+
+            if !next.is_null() {
+                self.release_head(next);
+            }
+
+            self.unreserve(1);
+            unsafe { WakerNode::release(node) };
+            return;
+        }
+
+        let mut prev = head;
+
+        loop {
+            let mut next = unsafe { (*prev).next.load(Ordering::Acquire) };
+
+            if next.is_null() {
+                // reached the end without finding it, so it was popped
+                // by someone else who will let go of it
+                if self.tail.load(Ordering::SeqCst) == prev {
+                    self.release_head(head);
+                    return;
+                }
+
+                next = unsafe { WakerNode::wait_next(prev, self.backoff) };
+            }
+
+            if next == node {
+                break;
+            }
+
+            prev = next;
+        }
+
+        unsafe {
+            let next = (*node).next.load(Ordering::Acquire);
+
+            if next.is_null() {
+                // node is the tail, so prev becomes the new one. prev.next is
+                // cleared first since registers will write to it as soon as it is.
+                (*prev).next.store(null_mut(), Ordering::Relaxed);
+
+                if self
+                    .tail
+                    .compare_exchange(node, prev, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    // a register got to the tail first and is linking behind node
+                    (*prev)
+                        .next
+                        .store(WakerNode::wait_next(node, self.backoff), Ordering::Release);
+                }
+            } else {
+                (*prev).next.store(next, Ordering::Release);
+            }
+        }
+
+        self.release_head(head);
+        self.unreserve(1);
+        unsafe { WakerNode::release(node) };
+    }
+
+    /// wakes the oldest waker in the WakerQueue and removes it from the queue.
+    ///
+    /// returns true if a waker was woken.
+    ///
+    /// this is thread safe.
+    pub fn wake_one(&self) -> bool {
+        self.wake_n(1) == 1
+    }
+
+    /// wakes the oldest waker in the WakerQueue, or stores a permit if there is none.
+    ///
+    /// a stored permit is consumed by the next [`register`](Self::register) or
+    /// [`notified`](Self::notified), which then completes immediately. at most one
+    /// permit is stored no matter how many times this is called.
+    ///
+    /// this is thread safe.
+    pub fn notify_one(&self) {
+        loop {
+            if self.wake_one() {
+                return;
+            }
+
+            // a swap rather than a store for the same reason as in release_head
+            self.permit.swap(true, Ordering::SeqCst);
+            // pairs with the fence in push
+            fence(Ordering::SeqCst);
+
+            // a register that raced with the wake_one above might have missed
+            // the permit, in which case we take it back and try again
+            if self.tail.load(Ordering::SeqCst).is_null() || !self.take_permit() {
+                return;
+            }
+        }
+    }
+
+    /// closes the WakerQueue and wakes all wakers in it.
+    ///
+    /// once closed, [`register`](Self::register) wakes the supplied waker right away
+    /// and [`notified`](Self::notified) completes immediately, so nothing can wait
+    /// on the queue anymore. a WakerQueue can't be reopened.
+    ///
+    /// this is thread safe.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.wake_all();
+    }
+
+    /// returns true if [`close`](Self::close) has been called.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// wakes up to `n` of the oldest wakers in the WakerQueue and removes them from the queue.
+    ///
+    /// cancelled registrations are skipped and don't count towards `n`.
+    ///
+    /// returns the number of wakers woken.
+    ///
+    /// this is thread safe.
+    pub fn wake_n(&self, n: usize) -> usize {
+        self.wake_front(n, true)
+    }
+
+    /// pops nodes off the front of the queue, waking them, until `n` of them have
+    /// been counted. cancelled nodes only count if `skip_cancelled` is false.
+    fn wake_front(&self, n: usize, skip_cancelled: bool) -> usize {
+        if n == 0 {
+            return 0;
+        }
+
+        let Some(mut head) = self.acquire_head() else {
+            return 0;
+        };
+
+        let mut woken = 0;
+
+        // nodes to wake once the head token is given back. wakers can run
+        // arbitrary code, including calls back into this queue, so they
+        // must not be woken while the token is held.
+        let mut to_wake: *mut WakerNode = null_mut();
+        let mut to_wake_tail: *mut WakerNode = null_mut();
+
+        loop {
+            // safety: acquire_head gave us the token and unlink_head hands it back
+            // for as long as the queue isn't empty
+            let next = unsafe { self.unlink_head(head) };
+
+            // safety: head was unlinked above, so it's ours to wake and release.
+            // nothing writes to its next anymore so it can be reused for to_wake.
+            unsafe {
+                match WakerNode::claim(head) {
+                    Claim::Wake => {
+                        woken += 1;
+
+                        (*head).next.store(null_mut(), Ordering::Relaxed);
+
+                        match to_wake_tail.as_ref() {
+                            Some(last) => last.next.store(head, Ordering::Relaxed),
+                            None => to_wake = head,
+                        }
+
+                        to_wake_tail = head;
+                    }
+                    claim => {
+                        if matches!(claim, Claim::Handled) || !skip_cancelled {
+                            woken += 1;
+                        }
+
+                        WakerNode::release(head);
+                    }
+                }
+            }
+
+            self.unreserve(1);
+
+            if next.is_null() {
+                break;
+            }
+
+            if woken == n {
+                // give the token back so other wakers can make progress
+                self.release_head(next);
+                break;
+            }
+
+            head = next;
+        }
+
+        while !to_wake.is_null() {
+            // safety: every node in to_wake was claimed above and is still ours
+            unsafe {
+                let next = (*to_wake).next.load(Ordering::Relaxed);
+                let waker = WakerNode::take_claimed(to_wake);
+                WakerNode::release(to_wake);
+                to_wake = next;
+
+                if let Some(w) = waker {
+                    w.wake();
+                }
+            }
+        }
+
+        woken
+    }
+
     /// wakes all wakers in the WakerQueue and clears it.
     ///
+    /// this never waits on other threads. if a register or a wake is halfway
+    /// through when this is called, whichever of them finishes last wakes what's
+    /// left, so some wakers may only be woken shortly after this returns.
+    ///
     /// this is thread safe.
     pub fn wake_all(&self) {
-        let tail = self.tail.swap(null_mut::<WakerNode>(), Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::SeqCst);
 
-        // tail being null implies nothing has been pushed into the queue
-        if tail.is_null() {
-            return;
+        // pairs with the fence in push and release_head
+        fence(Ordering::SeqCst);
+
+        // whoever has the token wakes everything when giving it back
+        if let Some(head) = self.try_acquire_head() {
+            unsafe { self.wake_all_from(head) };
         }
+    }
 
-        let mut head = self.head.swap(null_mut::<WakerNode>(), Ordering::Relaxed);
+    /// detaches the whole queue and wakes it.
+    ///
+    /// # Safety
+    ///
+    /// the caller must hold the head token for `head`.
+    unsafe fn wake_all_from(&self, head: *mut WakerNode) {
+        self.woken
+            .store(self.generation.load(Ordering::SeqCst), Ordering::Relaxed);
 
-        // if tail isn't null we are just waiting for a register
-        // to finish setting the head
-        while head.is_null() {
-            head = self.head.swap(null_mut::<WakerNode>(), Ordering::Acquire);
+        // holding head means nobody else can empty the queue, so tail isn't null.
+        // swapping it out detaches the whole list and lets registers start a new one.
+        let tail = self.tail.swap(null_mut::<WakerNode>(), Ordering::AcqRel);
+
+        // safety: tail is part of the detached list, and nothing links after it
+        // anymore since the next register sees a null tail
+        unsafe {
+            (*tail).next.store(END, Ordering::Relaxed);
+            self.wake_detached(head);
         }
+    }
 
-        // safety: we know head isn't null from above
-        let mut head = unsafe { Box::from_raw(head) };
+    /// wakes every node of a detached list, from `node` up to the one marked END.
+    ///
+    /// a node that hasn't been linked to the one after it yet isn't waited for.
+    /// it's marked DEFERRED instead, and the register linking it lets go of it
+    /// and wakes the rest of the list, see push.
+    ///
+    /// # Safety
+    ///
+    /// the list from `node` on must be detached, and the caller must own the queue's
+    /// references to all of its nodes.
+    unsafe fn wake_detached(&self, mut node: *mut WakerNode) {
+        loop {
+            // safety: the node is ours until it's released or handed off below
+            let waker = unsafe { WakerNode::notify(node) };
+            let mut next = unsafe { (*node).next.load(Ordering::Acquire) };
 
-        if let Some(w) = head.waker.take() {
-            w.wake();
-        }
+            if next.is_null() {
+                match unsafe {
+                    (*node).next.compare_exchange(
+                        null_mut(),
+                        DEFERRED,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                } {
+                    Ok(_) => {
+                        // the node belongs to the register now
+                        self.unreserve(1);
 
-        while head.as_ref() as *const _ != tail {
-            head = loop {
-                let next = head.next.load(Ordering::Acquire);
+                        if let Some(w) = waker {
+                            w.wake();
+                        }
 
-                if next.is_null() {
-                    std::hint::spin_loop();
-                    continue;
+                        return;
+                    }
+                    Err(linked) => next = linked,
                 }
+            }
 
-                break unsafe { Box::from_raw(next) };
-            };
+            // let go before waking, so whoever is woken never has to wait for us
+            unsafe { WakerNode::release(node) };
+            self.unreserve(1);
 
-            if let Some(w) = head.waker.take() {
+            if let Some(w) = waker {
                 w.wake();
             }
+
+            if next == END {
+                return;
+            }
+
+            node = next;
         }
     }
 }
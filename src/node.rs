@@ -0,0 +1,336 @@
+use core::{
+    cell::UnsafeCell,
+    ptr::{self, null_mut},
+    task::Waker,
+};
+
+#[cfg(feature = "alloc")]
+use alloc::alloc::{handle_alloc_error, Layout};
+#[cfg(feature = "alloc")]
+use core::ptr::NonNull;
+
+use crate::backoff::Backoff;
+#[cfg(futex)]
+use crate::futex;
+#[cfg(feature = "node-pool")]
+use crate::pool;
+#[cfg(feature = "alloc")]
+use crate::sync::{alloc, dealloc};
+use crate::sync::{const_fn, AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+
+/// set while the owner of the node is writing a new waker into it.
+pub(crate) const REGISTERING: usize = 0b0001;
+/// set once the node has been woken by the queue.
+pub(crate) const NOTIFIED: usize = 0b0010;
+/// set once the owner of the node has given up on it.
+pub(crate) const CANCELLED: usize = 0b0100;
+/// set for nodes that live inside a future rather than on the heap.
+pub(crate) const INLINE: usize = 0b1000;
+/// set for inline nodes of a thread blocked on the node's futex word rather than
+/// a waker, see `blocking`.
+#[cfg(futex)]
+pub(crate) const FUTEX: usize = 0b1_0000;
+
+/// stored in the next of the last node of a list detached by `wake_all`.
+///
+/// neither this nor DEFERRED is ever a valid node, nodes are at least pointer aligned.
+pub(crate) const END: *mut WakerNode = ptr::without_provenance_mut(1);
+/// stored in the next of a node by a `wake_all` that got to it before the register
+/// after it linked it, leaving the rest of the list to that register.
+pub(crate) const DEFERRED: *mut WakerNode = ptr::without_provenance_mut(2);
+
+/// outcome of [`WakerNode::claim`].
+pub(crate) enum Claim {
+    /// the registration was cancelled, there is nothing to wake.
+    Cancelled,
+    /// the registration is updating its waker and will wake it itself.
+    Handled,
+    /// the caller has to wake the waker.
+    Wake,
+}
+
+/// what has to be woken for a node, taken out of it by [`WakerNode::take_claimed`].
+pub(crate) enum Wakeup {
+    Waker(Waker),
+    /// the futex word of a blocked thread. the node may be gone by the time this is
+    /// woken, but waking a futex only uses its address, never what's behind it.
+    #[cfg(futex)]
+    Futex(*const AtomicU32),
+}
+
+impl Wakeup {
+    pub(crate) fn wake(self) {
+        match self {
+            Wakeup::Waker(waker) => waker.wake(),
+            #[cfg(futex)]
+            Wakeup::Futex(word) => futex::wake(word),
+        }
+    }
+}
+
+pub(crate) struct WakerNode {
+    pub(crate) next: AtomicPtr<WakerNode>,
+    pub(crate) state: AtomicUsize,
+    /// one reference is held by the queue until the node is popped,
+    /// and one by the [`Registration`](crate::Registration) if there is one.
+    ///
+    /// inline nodes are owned by their future and only count the queue's reference,
+    /// which the future waits on before it goes away.
+    pub(crate) refs: AtomicU32,
+    /// set to 1 once a FUTEX node has been woken. on 64-bit targets it fits in
+    /// the padding after refs, on 32-bit ones it adds a word to every node.
+    #[cfg(futex)]
+    pub(crate) futex: AtomicU32,
+    pub(crate) waker: UnsafeCell<Option<Waker>>,
+}
+
+impl WakerNode {
+    const_fn! {
+        /// creates a node to be embedded in a future.
+        pub(crate) fn inline() -> Self {
+            WakerNode {
+                next: AtomicPtr::new(null_mut()),
+                state: AtomicUsize::new(INLINE),
+                refs: AtomicU32::new(0),
+                #[cfg(futex)]
+                futex: AtomicU32::new(0),
+                waker: UnsafeCell::new(None),
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn alloc(waker: Waker, refs: u32) -> NonNull<WakerNode> {
+        match Self::try_alloc(waker, refs) {
+            Ok(node) => node,
+            Err(_) => handle_alloc_error(Layout::new::<WakerNode>()),
+        }
+    }
+
+    /// allocates a node, handing the waker back if the allocator fails.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn try_alloc(waker: Waker, refs: u32) -> Result<NonNull<WakerNode>, Waker> {
+        #[cfg(feature = "node-pool")]
+        let recycled = pool::take();
+        #[cfg(not(feature = "node-pool"))]
+        let recycled: Option<*mut WakerNode> = None;
+
+        // safety: WakerNode isn't zero sized
+        let Some(node) = recycled
+            .or_else(|| Some(unsafe { alloc(Layout::new::<WakerNode>()) }.cast()))
+            .and_then(NonNull::new)
+        else {
+            return Err(waker);
+        };
+
+        // safety: either freshly allocated with the layout of a WakerNode, so it can
+        // later be freed through WakerNode::free, or a recycled node without a waker
+        // that can be overwritten as is
+        unsafe {
+            node.as_ptr().write(WakerNode {
+                next: AtomicPtr::new(null_mut()),
+                state: AtomicUsize::new(0),
+                refs: AtomicU32::new(refs),
+                #[cfg(futex)]
+                futex: AtomicU32::new(0),
+                waker: UnsafeCell::new(Some(waker)),
+            })
+        };
+
+        Ok(node)
+    }
+
+    pub(crate) fn is_notified(&self) -> bool {
+        self.state.load(Ordering::Acquire) & NOTIFIED != 0
+    }
+
+    /// replaces the waker in place, see [`Registration::update`](crate::Registration::update).
+    ///
+    /// must only be called by the owner of the node.
+    pub(crate) fn update(&self, waker: &Waker) {
+        let flags = self.state.load(Ordering::Relaxed) & INLINE;
+
+        if self
+            .state
+            .compare_exchange(
+                flags,
+                flags | REGISTERING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // the only other thing that can be set while the owner
+            // holds on to the node is NOTIFIED
+            waker.wake_by_ref();
+            return;
+        }
+
+        // safety: REGISTERING gives us sole access to the waker
+        let slot = unsafe { &mut *self.waker.get() };
+
+        let old = match slot {
+            Some(w) if w.will_wake(waker) => None,
+            _ => slot.replace(waker.clone()),
+        };
+
+        if self
+            .state
+            .compare_exchange(
+                flags | REGISTERING,
+                flags,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // the queue notified us while we were swapping and
+            // left it to us to wake the new waker
+            let w = slot.take();
+            self.state.store(flags | NOTIFIED, Ordering::Release);
+
+            if let Some(w) = w {
+                w.wake();
+            }
+        }
+
+        drop(old);
+    }
+
+    /// marks the node as cancelled and drops its waker unless the queue got to it first.
+    ///
+    /// returns true if the queue got to it first.
+    ///
+    /// must only be called by the owner of the node.
+    pub(crate) fn cancel(&self) -> bool {
+        let prev = self.state.fetch_or(CANCELLED, Ordering::AcqRel);
+
+        if prev & NOTIFIED != 0 {
+            return true;
+        }
+
+        // safety: setting CANCELLED before the queue set NOTIFIED
+        // means the queue will never touch the waker
+        drop(unsafe { (*self.waker.get()).take() });
+        false
+    }
+
+    /// marks the node as notified and takes out what has to be woken, if anything.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a live node that was popped from the queue.
+    pub(crate) unsafe fn notify(this: *mut WakerNode) -> Option<Wakeup> {
+        match unsafe { WakerNode::claim(this) } {
+            Claim::Wake => unsafe { WakerNode::take_claimed(this) },
+            Claim::Cancelled | Claim::Handled => None,
+        }
+    }
+
+    /// marks the node as notified without waking it yet.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a live node that was popped from the queue.
+    pub(crate) unsafe fn claim(this: *mut WakerNode) -> Claim {
+        let prev = unsafe { (*this).state.fetch_or(NOTIFIED, Ordering::AcqRel) };
+
+        if prev & CANCELLED != 0 {
+            return Claim::Cancelled;
+        }
+
+        // the registration is halfway through swapping the waker and
+        // will wake the new one itself once it sees NOTIFIED
+        if prev & REGISTERING != 0 {
+            return Claim::Handled;
+        }
+
+        Claim::Wake
+    }
+
+    /// takes what has to be woken out of a node claimed with [`Claim::Wake`].
+    ///
+    /// this has to be called before the queue lets go of the node.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a live node that the caller claimed with [`Claim::Wake`].
+    pub(crate) unsafe fn take_claimed(this: *mut WakerNode) -> Option<Wakeup> {
+        #[cfg(futex)]
+        if unsafe { (*this).state.load(Ordering::Relaxed) } & FUTEX != 0 {
+            // the blocked thread returns once it sees this, and only goes away
+            // once the queue has let go of the node
+            unsafe { (*this).futex.store(1, Ordering::Release) };
+            return Some(Wakeup::Futex(unsafe { &raw const (*this).futex }));
+        }
+
+        // safety: NOTIFIED without REGISTERING gives us sole access to the waker
+        unsafe { (*(*this).waker.get()).take() }.map(Wakeup::Waker)
+    }
+
+    /// waits until a register links the node to the one after it.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a live node that isn't the tail of the queue.
+    pub(crate) unsafe fn wait_next(this: *mut WakerNode, backoff: &dyn Backoff) -> *mut WakerNode {
+        let mut step = 0;
+
+        loop {
+            let next = unsafe { (*this).next.load(Ordering::Acquire) };
+
+            if !next.is_null() {
+                return next;
+            }
+
+            backoff.snooze(step);
+            step = step.saturating_add(1);
+        }
+    }
+
+    /// drops a reference to the node and frees it if it was the last one.
+    ///
+    /// inline nodes are never freed, their future is waiting for the last
+    /// reference to go away instead. the node must not be touched after this.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a live node and the caller must own one of its references.
+    pub(crate) unsafe fn release(this: *mut WakerNode) {
+        // read before letting go, an inline node can disappear as soon as refs hits zero
+        let inline = unsafe { (*this).state.load(Ordering::Relaxed) } & INLINE != 0;
+
+        let last = unsafe { (*this).refs.fetch_sub(1, Ordering::AcqRel) } == 1;
+
+        // without alloc every node is inline
+        #[cfg(feature = "alloc")]
+        if last && !inline {
+            #[cfg(feature = "node-pool")]
+            unsafe {
+                drop((*(*this).waker.get()).take());
+
+                if pool::put(this) {
+                    return;
+                }
+            }
+
+            unsafe { WakerNode::free(this) };
+        }
+
+        #[cfg(not(feature = "alloc"))]
+        let _ = (last, inline);
+    }
+
+    /// drops a node allocated by [`try_alloc`](Self::try_alloc) and gives its memory back.
+    ///
+    /// # Safety
+    ///
+    /// `this` must be a node allocated by `try_alloc` that nobody references anymore.
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn free(this: *mut WakerNode) {
+        unsafe {
+            this.drop_in_place();
+            dealloc(this.cast(), Layout::new::<WakerNode>());
+        }
+    }
+}
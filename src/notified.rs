@@ -0,0 +1,177 @@
+use core::{
+    future::Future,
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr,
+    task::{Context, Poll},
+};
+
+use crate::{node::WakerNode, sync::Ordering, WakerQueue};
+#[cfg(futex)]
+use crate::{
+    node::{FUTEX, INLINE},
+    sync::AtomicU32,
+};
+
+/// future returned by [`WakerQueue::notified`].
+///
+/// the node registered with the queue lives inside the future itself, so waiting
+/// never allocates. dropping the future takes the node back out of the queue,
+/// which walks the queue up to it if it hasn't been woken yet.
+///
+/// the generation of the queue is captured when the future is created and
+/// checked again right after the node is pushed on the first poll. since
+/// `wake_all` bumps the generation before it takes the list, any `wake_all`
+/// that raced with the registration is caught by one or the other.
+pub struct Notified<'a> {
+    queue: &'a WakerQueue,
+    generation: usize,
+    state: State,
+    node: WakerNode,
+    _pinned: PhantomPinned,
+}
+
+enum State {
+    Init,
+    Waiting,
+    Done,
+}
+
+// safety: the node is only shared with the queue, which goes through
+// atomics and the node state. everything else needs a Pin<&mut Self>.
+unsafe impl Sync for Notified<'_> {}
+
+impl<'a> Notified<'a> {
+    pub(crate) fn new(queue: &'a WakerQueue) -> Self {
+        Notified {
+            queue,
+            generation: queue.generation(),
+            state: State::Init,
+            node: WakerNode::inline(),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    fn node_ptr(&self) -> *mut WakerNode {
+        ptr::from_ref(&self.node).cast_mut()
+    }
+
+    /// sets the node up with `init` and pushes it onto the queue, returning true
+    /// if the future is already done.
+    fn register(&mut self, init: impl FnOnce(&WakerNode)) -> bool {
+        let queue = self.queue;
+
+        // a full queue that can't take us is treated like a spurious wake
+        if queue.is_closed() || queue.take_permit() || !queue.reserve() {
+            self.state = State::Done;
+            return true;
+        }
+
+        init(&self.node);
+        self.node.refs.store(1, Ordering::Relaxed);
+
+        queue.push(self.node_ptr());
+        self.state = State::Waiting;
+
+        // a wake_all between creating the future and registering above might have
+        // missed us. the node is taken straight back out, so a wake_one can't be
+        // spent on a future that's already done.
+        if queue.generation() != self.generation || self.node.is_notified() {
+            self.take_back();
+            return true;
+        }
+
+        false
+    }
+
+    /// takes the node back out of the queue if it's in there, returning true if
+    /// it was woken before that.
+    fn take_back(&mut self) -> bool {
+        let woken = match self.state {
+            State::Init => false,
+            State::Waiting => {
+                let woken = self.node.cancel();
+
+                // safety: the node was pushed onto this queue and has been cancelled
+                unsafe { self.queue.remove(self.node_ptr()) };
+                woken
+            }
+            State::Done => true,
+        };
+
+        self.state = State::Done;
+        woken
+    }
+
+    /// registers a thread that waits on [`futex_word`](Self::futex_word) rather
+    /// than polling the future, returning true if it's already done.
+    ///
+    /// the word goes from 0 to 1 when the node is woken. the future must not be
+    /// polled afterwards.
+    #[cfg(futex)]
+    pub(crate) fn register_futex(self: Pin<&mut Self>) -> bool {
+        // safety: nothing is moved out of the future
+        let this = unsafe { self.get_unchecked_mut() };
+
+        match this.state {
+            State::Init => this.register(|node| {
+                node.state.store(INLINE | FUTEX, Ordering::Relaxed);
+            }),
+            State::Waiting | State::Done => true,
+        }
+    }
+
+    #[cfg(futex)]
+    pub(crate) fn futex_word(&self) -> &AtomicU32 {
+        &self.node.futex
+    }
+
+    /// takes the node back out of the queue, returning true if it was woken before
+    /// that. the future completes right away if polled again.
+    #[cfg(feature = "std")]
+    pub(crate) fn cancel(self: Pin<&mut Self>) -> bool {
+        // safety: nothing is moved out of the future
+        unsafe { self.get_unchecked_mut() }.take_back()
+    }
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // safety: nothing is moved out of the future
+        let this = unsafe { self.get_unchecked_mut() };
+
+        match this.state {
+            State::Init => {
+                // safety: nobody else can see the node until it's pushed
+                let init =
+                    |node: &WakerNode| unsafe { *node.waker.get() = Some(cx.waker().clone()) };
+
+                if this.register(init) {
+                    return Poll::Ready(());
+                }
+            }
+            State::Waiting => {
+                if this.node.is_notified() {
+                    return Poll::Ready(());
+                }
+
+                this.node.update(cx.waker());
+
+                if this.node.is_notified() {
+                    return Poll::Ready(());
+                }
+            }
+            State::Done => return Poll::Ready(()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        self.take_back();
+    }
+}
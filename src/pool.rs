@@ -0,0 +1,254 @@
+//! recycling of waker node allocations.
+//!
+//! with the `node-pool` feature, nodes freed by a wake are kept around and handed
+//! out again to the next register instead of going back to the allocator.
+//!
+//! every thread keeps a small cache of nodes it can use without any synchronization.
+//! once it fills up it's handed over to a shared lock-free free list, which a thread
+//! takes over in one go when its own cache runs dry. this way the common pattern
+//! of one thread registering and another one waking still recycles nodes.
+//!
+//! the shared free list is bounded by [`capacity`] and every thread cache by
+//! [`local_capacity`], anything past that is freed.
+
+use std::{cell::Cell, ptr::null_mut};
+
+use crate::{
+    sync::{AtomicPtr, AtomicUsize, Ordering},
+    Padded, WakerNode,
+};
+
+/// default number of nodes kept in the shared free list.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// default number of nodes a thread keeps for itself before handing them to the
+/// shared free list.
+pub const DEFAULT_LOCAL_CAPACITY: usize = 256;
+
+/// treiber stack of free nodes, linked through their next pointers.
+///
+/// nodes are only ever pushed and popped a whole thread cache at a time. since
+/// nothing is ever popped with a compare and swap it's free of the ABA problem.
+static SHARED: Padded<AtomicPtr<WakerNode>> = Padded::new(AtomicPtr::new(null_mut()));
+/// approximate length of SHARED.
+static SHARED_LEN: Padded<AtomicUsize> = Padded::new(AtomicUsize::new(0));
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static LOCAL_CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_LOCAL_CAPACITY);
+
+struct LocalCache {
+    head: Cell<*mut WakerNode>,
+    /// last node in the cache, so the whole cache can be handed over at once.
+    tail: Cell<*mut WakerNode>,
+    len: Cell<usize>,
+}
+
+impl LocalCache {
+    fn pop(&self) -> Option<*mut WakerNode> {
+        let head = self.head.get();
+
+        if head.is_null() {
+            return None;
+        }
+
+        // safety: nodes in the cache belong to this thread alone
+        let next = unsafe { (*head).next.load(Ordering::Relaxed) };
+
+        self.head.set(next);
+        self.len.set(self.len.get() - 1);
+
+        if next.is_null() {
+            self.tail.set(null_mut());
+        }
+
+        Some(head)
+    }
+
+    fn push(&self, node: *mut WakerNode) {
+        // safety: the caller gave up the node
+        unsafe { (*node).next.store(self.head.get(), Ordering::Relaxed) };
+
+        if self.head.get().is_null() {
+            self.tail.set(node);
+        }
+
+        self.head.set(node);
+        self.len.set(self.len.get() + 1);
+    }
+
+    /// takes over every node in the shared free list.
+    ///
+    /// only called when the cache is empty.
+    fn refill(&self) {
+        let head = SHARED.swap(null_mut(), Ordering::Acquire);
+
+        if head.is_null() {
+            return;
+        }
+
+        let mut tail = head;
+        let mut len = 1;
+
+        // safety: the whole list is ours after the swap
+        loop {
+            let next = unsafe { (*tail).next.load(Ordering::Relaxed) };
+
+            if next.is_null() {
+                break;
+            }
+
+            tail = next;
+            len += 1;
+        }
+
+        SHARED_LEN.fetch_sub(len, Ordering::Relaxed);
+
+        self.head.set(head);
+        self.tail.set(tail);
+        self.len.set(len);
+    }
+
+    /// hands the whole cache over to the shared free list, if it has room for it.
+    fn flush(&self) -> bool {
+        let len = self.len.get();
+
+        if SHARED_LEN.fetch_add(len, Ordering::Relaxed) + len > capacity() {
+            SHARED_LEN.fetch_sub(len, Ordering::Relaxed);
+            return false;
+        }
+
+        let (head, tail) = (self.head.get(), self.tail.get());
+        let mut shared = SHARED.load(Ordering::Relaxed);
+
+        loop {
+            // safety: the cached nodes are ours until the push below succeeds
+            unsafe { (*tail).next.store(shared, Ordering::Relaxed) };
+
+            match SHARED.compare_exchange_weak(shared, head, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => shared = current,
+            }
+        }
+
+        self.head.set(null_mut());
+        self.tail.set(null_mut());
+        self.len.set(0);
+
+        true
+    }
+
+    fn clear(&self) {
+        while let Some(node) = self.pop() {
+            // safety: cached nodes are valid, empty WakerNodes
+            unsafe { WakerNode::free(node) };
+        }
+    }
+}
+
+impl Drop for LocalCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+thread_local! {
+    static LOCAL: LocalCache = const {
+        LocalCache {
+            head: Cell::new(null_mut()),
+            tail: Cell::new(null_mut()),
+            len: Cell::new(0),
+        }
+    };
+}
+
+/// returns the number of nodes the shared free list holds on to.
+pub fn capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// sets the number of nodes the shared free list holds on to.
+///
+/// the caches of every thread come on top of this, see [`set_local_capacity`].
+/// this doesn't free anything right away, see [`trim`] for that.
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// returns the number of nodes every thread keeps for itself.
+pub fn local_capacity() -> usize {
+    LOCAL_CAPACITY.load(Ordering::Relaxed)
+}
+
+/// sets the number of nodes every thread keeps for itself, so the pool holds on to
+/// at most `capacity() + threads * local_capacity()` nodes. 0 turns the pool off.
+///
+/// a cache that's already over the new bound only shrinks as it's handed over to
+/// the shared free list, see [`trim`] to free it right away.
+pub fn set_local_capacity(capacity: usize) {
+    LOCAL_CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// returns roughly how many nodes the shared free list holds.
+///
+/// nodes in the caches of threads aren't counted.
+pub fn len() -> usize {
+    SHARED_LEN.load(Ordering::Relaxed)
+}
+
+/// frees every node in the shared free list and in the calling thread's cache.
+///
+/// caches of other threads are freed when those threads exit.
+pub fn trim() {
+    let _ = LOCAL.try_with(|local| {
+        local.refill();
+        local.clear();
+    });
+
+    let mut head = SHARED.swap(null_mut(), Ordering::Acquire);
+
+    while !head.is_null() {
+        SHARED_LEN.fetch_sub(1, Ordering::Relaxed);
+
+        // safety: the whole list is ours after the swap
+        let next = unsafe { (*head).next.load(Ordering::Relaxed) };
+        unsafe { WakerNode::free(head) };
+        head = next;
+    }
+}
+
+/// takes a free node out of the pool.
+///
+/// the node is a valid WakerNode without a waker, and can be overwritten as is.
+pub(crate) fn take() -> Option<*mut WakerNode> {
+    LOCAL
+        .try_with(|local| {
+            local.pop().or_else(|| {
+                local.refill();
+                local.pop()
+            })
+        })
+        .ok()
+        .flatten()
+}
+
+/// puts a node back into the pool.
+///
+/// returns false if the pool is full, in which case the node should be freed.
+///
+/// # Safety
+///
+/// `node` must be a valid WakerNode without a waker that nobody else references.
+pub(crate) unsafe fn put(node: *mut WakerNode) -> bool {
+    LOCAL
+        .try_with(|local| {
+            if local.len.get() >= local_capacity() {
+                // an empty cache has nothing to hand over
+                if local.len.get() == 0 || !local.flush() {
+                    return false;
+                }
+            }
+
+            local.push(node);
+            true
+        })
+        .unwrap_or(false)
+}
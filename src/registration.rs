@@ -0,0 +1,75 @@
+use core::{marker::PhantomData, ptr::NonNull, task::Waker};
+
+use crate::{
+    node::{WakerNode, NOTIFIED},
+    sync::Ordering,
+    WakerQueue,
+};
+
+/// a handle to a waker registered with [`WakerQueue::register_handle`].
+///
+/// dropping the handle cancels the registration: the waker is dropped right away
+/// and will not be woken. the (now empty) node stays in the queue until the next
+/// wake reaches it, at which point it is skipped and freed.
+pub struct Registration<'a> {
+    pub(crate) node: NonNull<WakerNode>,
+    pub(crate) _queue: PhantomData<&'a WakerQueue>,
+}
+
+// safety: the node is only touched through atomics, and the waker inside
+// is guarded by the node state.
+unsafe impl Send for Registration<'_> {}
+unsafe impl Sync for Registration<'_> {}
+
+impl Registration<'_> {
+    /// returns true if the waker has been woken by the queue.
+    pub fn is_notified(&self) -> bool {
+        unsafe { self.node.as_ref() }.is_notified()
+    }
+
+    /// replaces the registered waker in place.
+    ///
+    /// meant to be called on every poll after the first one, so a future that is
+    /// polled many times before being woken still only occupies one node.
+    /// if `waker` would wake the same task as the registered one nothing is swapped.
+    ///
+    /// if the registration has already been notified `waker` is woken right away.
+    pub fn update(&mut self, waker: &Waker) {
+        unsafe { self.node.as_ref() }.update(waker);
+    }
+
+    /// creates a registration that is already notified from a node that was never
+    /// pushed, waking its waker.
+    pub(crate) fn notified(node: NonNull<WakerNode>) -> Self {
+        // safety: the node was never pushed, so it's ours alone
+        unsafe {
+            let node = node.as_ref();
+            node.state.store(NOTIFIED, Ordering::Relaxed);
+
+            if let Some(w) = (*node.waker.get()).take() {
+                w.wake();
+            }
+        }
+
+        Registration {
+            node,
+            _queue: PhantomData,
+        }
+    }
+
+    /// lets go of the handle without cancelling the registration.
+    pub(crate) fn detach(self) {
+        let node = self.node.as_ptr();
+        core::mem::forget(self);
+        unsafe { WakerNode::release(node) };
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            self.node.as_ref().cancel();
+            WakerNode::release(self.node.as_ptr());
+        }
+    }
+}
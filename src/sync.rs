@@ -0,0 +1,88 @@
+//! the atomics used throughout the crate.
+//!
+//! with the portable-atomic feature they come from the portable-atomic crate instead
+//! of core, which falls back to critical sections on targets without native
+//! compare and swap, like thumbv6m and some RISC-V cores.
+//!
+//! when built with `--cfg loom` they come from loom instead, along with the
+//! allocator and the spin and yield hints, so the loom tests in `tests/loom.rs`
+//! can explore every interleaving of them. `--cfg shuttle` does the same for the
+//! randomized schedules in `tests/shuttle.rs`.
+
+#[cfg(all(not(loom), not(shuttle), not(feature = "portable-atomic")))]
+pub(crate) use core::sync::atomic::{
+    fence, AtomicBool, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering,
+};
+
+#[cfg(all(not(loom), not(shuttle), feature = "portable-atomic"))]
+pub(crate) use portable_atomic::{
+    fence, AtomicBool, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering,
+};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{
+    fence, AtomicBool, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering,
+};
+
+#[cfg(shuttle)]
+pub(crate) use shuttle::sync::atomic::{
+    fence, AtomicBool, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering,
+};
+
+#[cfg(all(not(loom), feature = "alloc"))]
+pub(crate) use alloc::alloc::{alloc, dealloc};
+
+#[cfg(loom)]
+pub(crate) use loom::alloc::{alloc, dealloc};
+
+#[cfg(all(not(loom), not(shuttle)))]
+pub(crate) use core::hint::spin_loop;
+
+#[cfg(loom)]
+pub(crate) use loom::hint::spin_loop;
+
+#[cfg(shuttle)]
+pub(crate) use shuttle::hint::spin_loop;
+
+/// gives up the rest of the timeslice while waiting on another thread, or spins
+/// without std.
+pub(crate) fn yield_now() {
+    #[cfg(loom)]
+    loom::thread::yield_now();
+    #[cfg(shuttle)]
+    shuttle::thread::yield_now();
+    #[cfg(all(not(loom), not(shuttle), feature = "std"))]
+    std::thread::yield_now();
+    #[cfg(all(not(loom), not(shuttle), not(feature = "std")))]
+    spin_loop();
+}
+
+/// puts the thread to sleep for `timeout`.
+///
+/// under loom and shuttle this only yields, they have no notion of time.
+#[cfg(feature = "std")]
+pub(crate) fn sleep(timeout: core::time::Duration) {
+    #[cfg(any(loom, shuttle))]
+    {
+        let _ = timeout;
+        yield_now();
+    }
+    #[cfg(not(any(loom, shuttle)))]
+    std::thread::sleep(timeout);
+}
+
+/// defines a function that is const, except under loom whose atomics can't be
+/// created in a const context.
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const fn $($rest)*
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis fn $($rest)*
+    };
+}
+
+pub(crate) use const_fn;
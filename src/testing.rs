@@ -0,0 +1,207 @@
+//! wakers for testing code built on a WakerQueue.
+//!
+//! with the `testing` feature, [`CountingWaker`] counts how often it has been
+//! woken and [`RecordingWaker`] logs which waker was woken when into a shared
+//! [`WakeLog`], so tests can check for lost, doubled or reordered wakeups
+//! without hand rolling their own wakers.
+//!
+//! the assertions panic with a message describing what went wrong, and report
+//! the location of the caller.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Wake, Waker},
+};
+
+/// a waker that counts how often it has been woken.
+///
+/// clones share the same count, as do the wakers returned by [`waker`](Self::waker).
+#[derive(Clone, Default)]
+pub struct CountingWaker {
+    count: Arc<Counter>,
+}
+
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CountingWaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns a waker that adds to this count when woken.
+    pub fn waker(&self) -> Waker {
+        Waker::from(self.count.clone())
+    }
+
+    /// returns the number of times this has been woken.
+    pub fn count(&self) -> usize {
+        self.count.0.load(Ordering::SeqCst)
+    }
+
+    /// panics unless this has been woken exactly `n` times.
+    #[track_caller]
+    pub fn assert_woken(&self, n: usize) {
+        let count = self.count();
+        assert!(count == n, "expected {n} wakeups, got {count}");
+    }
+
+    /// panics unless this has been woken exactly once.
+    #[track_caller]
+    pub fn assert_woken_once(&self) {
+        self.assert_woken(1);
+    }
+
+    /// panics if this has been woken.
+    #[track_caller]
+    pub fn assert_not_woken(&self) {
+        self.assert_woken(0);
+    }
+}
+
+impl fmt::Debug for CountingWaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountingWaker")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+/// a shared log of [`RecordingWaker`] ids, in the order they were woken.
+///
+/// clones share the same log.
+#[derive(Clone, Default)]
+pub struct WakeLog {
+    ids: Arc<Mutex<Vec<usize>>>,
+}
+
+impl WakeLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns a waker that logs `id` when woken.
+    ///
+    /// shorthand for `RecordingWaker::new(id, self).waker()`.
+    pub fn waker(&self, id: usize) -> Waker {
+        RecordingWaker::new(id, self).waker()
+    }
+
+    /// returns the ids woken so far, oldest first.
+    pub fn order(&self) -> Vec<usize> {
+        self.lock().clone()
+    }
+
+    /// returns the number of times `id` has been woken.
+    pub fn count(&self, id: usize) -> usize {
+        self.lock().iter().filter(|&&woken| woken == id).count()
+    }
+
+    /// forgets every wakeup logged so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// panics unless `id` has been woken exactly once.
+    #[track_caller]
+    pub fn assert_woken_once(&self, id: usize) {
+        let count = self.count(id);
+        assert!(
+            count == 1,
+            "expected waker {id} to be woken once, got {count} wakeups"
+        );
+    }
+
+    /// panics if `id` has been woken.
+    #[track_caller]
+    pub fn assert_not_woken(&self, id: usize) {
+        let count = self.count(id);
+        assert!(
+            count == 0,
+            "expected waker {id} not to be woken, got {count} wakeups"
+        );
+    }
+
+    /// panics unless every one of `ids` has been woken exactly once, in any order.
+    #[track_caller]
+    pub fn assert_each_woken_once(&self, ids: impl IntoIterator<Item = usize>) {
+        for id in ids {
+            self.assert_woken_once(id);
+        }
+    }
+
+    /// panics unless exactly `ids` have been woken, each once and in that order.
+    ///
+    /// meant for checking that wakers are woken in the order they were registered.
+    #[track_caller]
+    pub fn assert_fifo(&self, ids: impl IntoIterator<Item = usize>) {
+        let expected: Vec<usize> = ids.into_iter().collect();
+        let order = self.order();
+        assert!(
+            order == expected,
+            "expected wakeups in order {expected:?}, got {order:?}"
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<usize>> {
+        // a panicking assertion in another thread doesn't make the log any less valid
+        self.ids
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for WakeLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("WakeLog").field(&*self.lock()).finish()
+    }
+}
+
+/// a waker that logs its id into a [`WakeLog`] when woken.
+#[derive(Clone, Debug)]
+pub struct RecordingWaker {
+    id: usize,
+    log: WakeLog,
+}
+
+impl RecordingWaker {
+    pub fn new(id: usize, log: &WakeLog) -> Self {
+        RecordingWaker {
+            id,
+            log: log.clone(),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// returns a waker that logs this id when woken.
+    pub fn waker(&self) -> Waker {
+        Waker::from(Arc::new(self.clone()))
+    }
+}
+
+impl Wake for RecordingWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.log.lock().push(self.id);
+    }
+}
@@ -0,0 +1,228 @@
+//! waiting on a WakerQueue with a deadline.
+//!
+//! deadlines are kept by a single timer thread shared by every WakerQueue, so
+//! timeouts work the same on any executor, or none. it's started by the first
+//! [`NotifiedTimeout`] that has to wait, and sleeps until the earliest deadline
+//! it holds.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{Condvar, Mutex, MutexGuard, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Elapsed, Notified, WakerQueue};
+
+impl WakerQueue {
+    /// returns a future that completes once the queue is woken, or fails with
+    /// [`Elapsed`] once `timeout` has passed.
+    ///
+    /// see [`notified_until`](Self::notified_until).
+    pub fn notified_timeout(&self, timeout: Duration) -> NotifiedTimeout<'_> {
+        // a deadline too far out to represent is as good as none
+        NotifiedTimeout::new(self.notified(), Instant::now().checked_add(timeout))
+    }
+
+    /// returns a future that completes once the queue is woken, or fails with
+    /// [`Elapsed`] once `deadline` has passed.
+    ///
+    /// this waits the same way a [`notified`](Self::notified) future does. once the
+    /// deadline has passed the future takes its waiter back out of the queue, so
+    /// it doesn't use up a `wake_one` meant for someone else. a wake that got to it
+    /// first still completes it.
+    pub fn notified_until(&self, deadline: Instant) -> NotifiedTimeout<'_> {
+        NotifiedTimeout::new(self.notified(), Some(deadline))
+    }
+}
+
+/// future returned by [`WakerQueue::notified_timeout`] and
+/// [`WakerQueue::notified_until`].
+pub struct NotifiedTimeout<'a> {
+    notified: Notified<'a>,
+    deadline: Option<Instant>,
+    /// the key of our waker in the timer, once we've had to wait.
+    key: Option<Key>,
+}
+
+impl<'a> NotifiedTimeout<'a> {
+    fn new(notified: Notified<'a>, deadline: Option<Instant>) -> Self {
+        NotifiedTimeout {
+            notified,
+            deadline,
+            key: None,
+        }
+    }
+}
+
+impl Future for NotifiedTimeout<'_> {
+    type Output = Result<(), Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // safety: notified is never moved out of the future
+        let NotifiedTimeout {
+            notified,
+            deadline,
+            key,
+        } = unsafe { self.get_unchecked_mut() };
+        let mut notified = unsafe { Pin::new_unchecked(notified) };
+
+        if notified.as_mut().poll(cx).is_ready() {
+            cancel_timer(key);
+            return Poll::Ready(Ok(()));
+        }
+
+        let Some(deadline) = *deadline else {
+            return Poll::Pending;
+        };
+
+        if Instant::now() >= deadline {
+            cancel_timer(key);
+
+            // a wake that raced with the deadline still counts
+            if notified.cancel() {
+                return Poll::Ready(Ok(()));
+            }
+
+            return Poll::Ready(Err(Elapsed(())));
+        }
+
+        match *key {
+            Some(key) => timer().update(key, cx.waker()),
+            None => *key = Some(timer().insert(deadline, cx.waker())),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for NotifiedTimeout<'_> {
+    fn drop(&mut self) {
+        cancel_timer(&mut self.key);
+    }
+}
+
+fn cancel_timer(key: &mut Option<Key>) {
+    if let Some(key) = key.take() {
+        timer().remove(key);
+    }
+}
+
+/// a deadline, and a counter that tells apart waiters with the same one.
+type Key = (Instant, u64);
+
+struct Timer {
+    state: Mutex<State>,
+    /// signalled when a deadline earlier than all the others is inserted.
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    wakers: BTreeMap<Key, Waker>,
+    next_id: u64,
+}
+
+/// returns the timer, starting its thread on first use.
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<Timer> = OnceLock::new();
+
+    TIMER.get_or_init(|| {
+        // the thread blocks on TIMER until this returns
+        thread::Builder::new()
+            .name("wake-queue-timer".into())
+            .spawn(|| timer().run())
+            .expect("failed to spawn the wake-queue timer thread");
+
+        Timer {
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    })
+}
+
+impl Timer {
+    fn insert(&self, deadline: Instant, waker: &Waker) -> Key {
+        let mut state = self.lock();
+
+        let key = (deadline, state.next_id);
+        state.next_id += 1;
+
+        let earliest = state
+            .wakers
+            .first_key_value()
+            .is_none_or(|(first, _)| key < *first);
+
+        state.wakers.insert(key, waker.clone());
+        drop(state);
+
+        if earliest {
+            self.changed.notify_one();
+        }
+
+        key
+    }
+
+    /// replaces the waker for `key`, unless it has already been woken.
+    fn update(&self, key: Key, waker: &Waker) {
+        if let Some(old) = self.lock().wakers.get_mut(&key) {
+            old.clone_from(waker);
+        }
+    }
+
+    fn remove(&self, key: Key) {
+        // dropped outside the lock, a waker can run arbitrary code when dropped
+        let waker = self.lock().wakers.remove(&key);
+        drop(waker);
+    }
+
+    fn run(&self) {
+        let mut due = Vec::new();
+        let mut state = self.lock();
+
+        loop {
+            let now = Instant::now();
+
+            while let Some(entry) = state.wakers.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+
+                due.push(entry.remove());
+            }
+
+            if !due.is_empty() {
+                // wakers can call back into the timer, so they're woken unlocked
+                drop(state);
+                due.drain(..).for_each(Waker::wake);
+                state = self.lock();
+                continue;
+            }
+
+            state = match state.wakers.first_key_value() {
+                Some(((deadline, _), _)) => {
+                    let timeout = *deadline - now;
+
+                    self.changed
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state is never left half updated, so a panic elsewhere doesn't matter
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
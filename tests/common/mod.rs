@@ -0,0 +1,131 @@
+//! helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::Duration,
+};
+
+use wake_queue::WakerQueue;
+
+/// a waker that counts how often it has been woken.
+pub struct Counter(AtomicUsize);
+
+impl Counter {
+    pub fn woken(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+pub fn counting_waker() -> (Waker, Arc<Counter>) {
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    (Waker::from(counter.clone()), counter)
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+struct Noop;
+
+impl Wake for Noop {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// a waker that does nothing when woken.
+pub fn noop_waker() -> Waker {
+    Waker::from(Arc::new(Noop))
+}
+
+/// runs a future to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+/// polls a future once, returning true if it completed.
+pub fn poll_once<F: Future>(future: Pin<&mut F>, waker: &Waker) -> bool {
+    future.poll(&mut Context::from_waker(waker)).is_ready()
+}
+
+/// scales an iteration count down when running under miri, which is
+/// orders of magnitude slower.
+pub fn scaled(n: usize) -> usize {
+    if cfg!(miri) {
+        (n / 100).max(2)
+    } else {
+        n
+    }
+}
+
+/// has 4 threads wait on a queue over and over with a short timeout, while
+/// wake_one is called about as often as they time out.
+///
+/// `wait` waits on the queue for at most the given time and returns true if it
+/// was woken.
+pub fn timeouts_race_with_wake_one<W>(wait: W)
+where
+    W: Fn(&WakerQueue, Duration) -> bool + Send + Sync + 'static,
+{
+    let queue = Arc::new(WakerQueue::new());
+    let wait = Arc::new(wait);
+    let completed = Arc::new(AtomicUsize::new(0));
+    let rounds = scaled(2000);
+
+    let waiters: Vec<_> = (0..4)
+        .map(|_| {
+            let (queue, wait, completed) = (queue.clone(), wait.clone(), completed.clone());
+
+            thread::spawn(move || {
+                for _ in 0..rounds {
+                    if wait(&queue, Duration::from_micros(20)) {
+                        completed.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut woken = 0;
+
+    // spaced out so that waiters time out about as often as they're woken
+    while waiters.iter().any(|handle| !handle.is_finished()) {
+        woken += usize::from(queue.wake_one());
+        thread::sleep(Duration::from_micros(10));
+    }
+
+    for handle in waiters {
+        handle.join().unwrap();
+    }
+
+    // a waiter timing out never takes a wake_one meant for someone else,
+    // and one woken as it times out still counts the wake
+    assert_eq!(completed.load(Ordering::SeqCst), woken);
+}
@@ -0,0 +1,292 @@
+//! loom model checks for the races between registering and waking.
+//!
+//! run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+//!
+//! wakers are backed by loom's Arc and nodes by loom's allocator, so every
+//! execution also fails on a leaked or double freed node or waker.
+
+#![cfg(loom)]
+
+use std::{
+    future::Future,
+    pin::pin,
+    task::{Context, RawWaker, RawWakerVTable, Waker},
+};
+
+use loom::{
+    future::block_on,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use wake_queue::{ArrayWakerQueue, WakerQueue};
+
+/// returns a waker along with the number of times it has been woken.
+fn counting_waker() -> (Waker, Arc<AtomicUsize>) {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+    unsafe fn clone(data: *const ()) -> RawWaker {
+        unsafe { Arc::increment_strong_count(data.cast::<AtomicUsize>()) };
+        RawWaker::new(data, &VTABLE)
+    }
+
+    unsafe fn wake(data: *const ()) {
+        unsafe {
+            wake_by_ref(data);
+            drop(data);
+        }
+    }
+
+    unsafe fn wake_by_ref(data: *const ()) {
+        unsafe { (*data.cast::<AtomicUsize>()).fetch_add(1, Ordering::SeqCst) };
+    }
+
+    unsafe fn drop(data: *const ()) {
+        unsafe { Arc::decrement_strong_count(data.cast::<AtomicUsize>()) };
+    }
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let data = Arc::into_raw(count.clone()).cast::<()>();
+
+    (
+        unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) },
+        count,
+    )
+}
+
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+
+    // the spin loops make an unbounded search explode
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+
+    builder.check(f);
+}
+
+#[test]
+fn register_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (w1, c1) = counting_waker();
+        let (w2, c2) = counting_waker();
+
+        let handles = [w1, w2].map(|waker| {
+            let queue = queue.clone();
+            thread::spawn(move || queue.register(waker))
+        });
+
+        queue.wake_all();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // anything the first wake_all missed is still queued
+        queue.wake_all();
+
+        assert_eq!(c1.load(Ordering::SeqCst), 1);
+        assert_eq!(c2.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn array_register_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(ArrayWakerQueue::<2>::new());
+        let ready = Arc::new(AtomicBool::new(false));
+        let (waker, count) = counting_waker();
+
+        let waiter = {
+            let (queue, ready) = (queue.clone(), ready.clone());
+
+            thread::spawn(move || {
+                queue.register(waker);
+                ready.load(Ordering::SeqCst)
+            })
+        };
+
+        ready.store(true, Ordering::SeqCst);
+        queue.wake_all();
+
+        // a waiter that missed the flag must have been seen by wake_all
+        let saw_ready = waiter.join().unwrap();
+        assert!(saw_ready || count.load(Ordering::SeqCst) == 1);
+
+        queue.wake_all();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn double_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (w1, c1) = counting_waker();
+        let (w2, c2) = counting_waker();
+
+        queue.register(w1);
+
+        let register = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.register(w2))
+        };
+
+        let wake = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.wake_all())
+        };
+
+        queue.wake_all();
+        register.join().unwrap();
+        wake.join().unwrap();
+
+        // the first waker was queued before either wake_all, so one of them
+        // (or whoever they left it to) must have woken it
+        assert_eq!(c1.load(Ordering::SeqCst), 1);
+
+        queue.wake_all();
+        assert_eq!(c2.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn wake_one_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (w1, c1) = counting_waker();
+        let (w2, c2) = counting_waker();
+
+        queue.register(w1);
+        queue.register(w2);
+
+        let wake = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.wake_one())
+        };
+
+        // if wake_one holds the head token this is left to it
+        queue.wake_all();
+        wake.join().unwrap();
+
+        assert_eq!(c1.load(Ordering::SeqCst), 1);
+        assert_eq!(c2.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn drop_vs_register() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (w1, c1) = counting_waker();
+        let (w2, c2) = counting_waker();
+
+        let handles = [w1, w2].map(|waker| {
+            let queue = queue.clone();
+            thread::spawn(move || queue.register(waker))
+        });
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // dropping the queue frees the nodes without waking them
+        drop(queue);
+
+        assert_eq!(c1.load(Ordering::SeqCst), 0);
+        assert_eq!(c2.load(Ordering::SeqCst), 0);
+    });
+}
+
+#[test]
+fn cancel_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (waker, count) = counting_waker();
+
+        let cancel = {
+            let queue = queue.clone();
+            thread::spawn(move || drop(queue.register_handle(waker)))
+        };
+
+        queue.wake_all();
+        cancel.join().unwrap();
+        queue.wake_all();
+
+        assert!(count.load(Ordering::SeqCst) <= 1);
+    });
+}
+
+#[test]
+fn notified_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let ready = Arc::new(AtomicBool::new(false));
+
+        let waiter = {
+            let (queue, ready) = (queue.clone(), ready.clone());
+
+            // a lost wakeup leaves this blocked forever, which loom reports as a deadlock
+            thread::spawn(move || {
+                block_on(async {
+                    loop {
+                        let notified = queue.notified();
+
+                        if ready.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        notified.await;
+                    }
+                })
+            })
+        };
+
+        ready.store(true, Ordering::SeqCst);
+        queue.wake_all();
+
+        waiter.join().unwrap();
+    });
+}
+
+#[test]
+fn notified_drop_vs_wake_all() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+        let (waker, _count) = counting_waker();
+
+        let waiter = {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                let mut notified = pin!(queue.notified());
+                let _ = notified.as_mut().poll(&mut Context::from_waker(&waker));
+            })
+        };
+
+        queue.wake_all();
+        waiter.join().unwrap();
+    });
+}
+
+#[test]
+fn notify_one_vs_notified() {
+    model(|| {
+        let queue = Arc::new(WakerQueue::new());
+
+        let waiter = {
+            let queue = queue.clone();
+            thread::spawn(move || block_on(queue.notified()))
+        };
+
+        queue.notify_one();
+        waiter.join().unwrap();
+    });
+}
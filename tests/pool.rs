@@ -0,0 +1,56 @@
+//! checks of the node pool behind the `node-pool` feature.
+//!
+//! the pool is global, so everything is checked from one test to keep other
+//! tests in this binary from putting nodes into it in between.
+
+#![cfg(not(any(loom, shuttle)))]
+
+mod common;
+
+use std::{sync::Arc, thread};
+
+use common::noop_waker;
+
+use wake_queue::{pool, WakerQueue};
+
+/// registers `n` wakers on the calling thread and wakes them on another one.
+fn register_here_wake_there(queue: &Arc<WakerQueue>, n: usize) {
+    for _ in 0..n {
+        queue.register(noop_waker());
+    }
+
+    let queue = queue.clone();
+    thread::spawn(move || queue.wake_all()).join().unwrap();
+}
+
+#[test]
+fn nodes_move_between_threads_within_the_bounds() {
+    let queue = Arc::new(WakerQueue::new());
+
+    pool::set_local_capacity(4);
+    pool::set_capacity(16);
+
+    // the waking thread hands its cache over 4 nodes at a time until the shared
+    // free list is full, and frees the rest
+    register_here_wake_there(&queue, 100);
+    assert_eq!(pool::len(), 16);
+
+    // a register on a thread with an empty cache takes over the whole list
+    queue.register(noop_waker());
+    assert_eq!(pool::len(), 0);
+    queue.wake_all();
+
+    register_here_wake_there(&queue, 100);
+    assert_eq!(pool::len(), 16);
+
+    pool::trim();
+    assert_eq!(pool::len(), 0);
+
+    // without a local cache nothing reaches the shared free list either
+    pool::set_local_capacity(0);
+    register_here_wake_there(&queue, 100);
+    assert_eq!(pool::len(), 0);
+
+    pool::set_local_capacity(pool::DEFAULT_LOCAL_CAPACITY);
+    pool::set_capacity(pool::DEFAULT_CAPACITY);
+}
@@ -0,0 +1,321 @@
+//! single threaded checks of the public api.
+//!
+//! these are cheap enough to run under miri as they are:
+//!
+//! ```text
+//! cargo +nightly miri test --test queue
+//! MIRIFLAGS="-Zmiri-tree-borrows" cargo +nightly miri test --test queue
+//! ```
+
+#![cfg(not(any(loom, shuttle)))]
+
+mod common;
+
+use std::{
+    pin::pin,
+    sync::Arc,
+    task::Wake,
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+use common::{block_on, counting_waker, noop_waker, poll_once};
+use wake_queue::{backoff::Park, ArrayWakerQueue, Backoff, Overflow, RegisterError, WakerQueue};
+
+#[test]
+fn wake_one_is_fifo() {
+    let queue = WakerQueue::new();
+    let (wakers, counters): (Vec<_>, Vec<_>) = (0..3).map(|_| counting_waker()).unzip();
+
+    for waker in wakers {
+        queue.register(waker);
+    }
+
+    for i in 0..3 {
+        assert!(queue.wake_one());
+        assert_eq!(counters[i].woken(), 1);
+
+        for counter in &counters[i + 1..] {
+            assert_eq!(counter.woken(), 0);
+        }
+    }
+
+    assert!(!queue.wake_one());
+}
+
+#[test]
+fn wake_n_and_wake_all() {
+    let queue = WakerQueue::new();
+    let (wakers, counters): (Vec<_>, Vec<_>) = (0..5).map(|_| counting_waker()).unzip();
+
+    for waker in wakers {
+        queue.register(waker);
+    }
+
+    assert_eq!(queue.wake_n(2), 2);
+    assert_eq!(counters.iter().map(|c| c.woken()).sum::<usize>(), 2);
+
+    queue.wake_all();
+    assert_eq!(queue.wake_n(2), 0);
+
+    for counter in &counters {
+        assert_eq!(counter.woken(), 1);
+    }
+}
+
+#[test]
+fn dropping_a_handle_cancels_it() {
+    let queue = WakerQueue::new();
+    let (first, first_count) = counting_waker();
+    let (second, second_count) = counting_waker();
+
+    drop(queue.register_handle(first));
+    let registration = queue.register_handle(second);
+
+    assert!(queue.wake_one());
+    assert!(registration.is_notified());
+    assert_eq!(first_count.woken(), 0);
+    assert_eq!(second_count.woken(), 1);
+}
+
+#[test]
+fn update_replaces_the_waker() {
+    let queue = WakerQueue::new();
+    let (old, old_count) = counting_waker();
+    let (new, new_count) = counting_waker();
+
+    let mut registration = queue.register_handle(old);
+    registration.update(&new);
+    queue.wake_all();
+
+    assert!(registration.is_notified());
+    assert_eq!(old_count.woken(), 0);
+    assert_eq!(new_count.woken(), 1);
+}
+
+#[test]
+fn notified_before_poll() {
+    let queue = WakerQueue::new();
+    let notified = queue.notified();
+
+    queue.wake_all();
+    block_on(notified);
+}
+
+#[test]
+fn notified_completed_by_an_earlier_wake_all_leaves_the_queue() {
+    let queue = WakerQueue::new();
+    let mut early = pin!(queue.notified());
+
+    queue.wake_all();
+    assert!(poll_once(early.as_mut(), &noop_waker()));
+
+    // the completed future is still around, but wake_one goes to the next waiter
+    let (waker, counter) = counting_waker();
+    let mut late = pin!(queue.notified());
+    assert!(!poll_once(late.as_mut(), &waker));
+
+    assert!(queue.wake_one());
+    assert_eq!(counter.woken(), 1);
+}
+
+#[test]
+fn dropping_a_pending_notified() {
+    let queue = WakerQueue::new();
+    let (waker, count) = counting_waker();
+
+    {
+        let mut notified = pin!(queue.notified());
+        assert!(!poll_once(notified.as_mut(), &waker));
+    }
+
+    queue.wake_all();
+    assert_eq!(count.woken(), 0);
+}
+
+#[test]
+fn notify_one_stores_a_permit() {
+    let queue = WakerQueue::new();
+
+    queue.notify_one();
+    block_on(queue.notified());
+
+    // the permit is used up, so a plain register waits again
+    let (waker, count) = counting_waker();
+    queue.register(waker);
+    assert_eq!(count.woken(), 0);
+
+    queue.notify_one();
+    assert_eq!(count.woken(), 1);
+}
+
+#[test]
+fn register_if_generation() {
+    let queue = WakerQueue::new();
+    let (waker, count) = counting_waker();
+
+    let generation = queue.generation();
+    queue.wake_all();
+    assert!(!queue.register_if_generation(generation, waker.clone()));
+
+    queue.wake_all();
+    assert_eq!(count.woken(), 0);
+
+    assert!(queue.register_if_generation(queue.generation(), waker));
+    queue.wake_all();
+    assert_eq!(count.woken(), 1);
+}
+
+#[test]
+fn close_wakes_everything() {
+    let queue = WakerQueue::new();
+    let (waker, count) = counting_waker();
+
+    queue.register(waker.clone());
+    queue.close();
+    assert!(queue.is_closed());
+    assert_eq!(count.woken(), 1);
+
+    queue.register(waker.clone());
+    assert_eq!(count.woken(), 2);
+    assert!(matches!(
+        queue.try_register(waker),
+        Err(RegisterError::Closed(_))
+    ));
+
+    block_on(queue.notified());
+}
+
+#[test]
+fn overflow_reject() {
+    let queue = WakerQueue::with_capacity(2, Overflow::Reject);
+    let (wakers, counters): (Vec<_>, Vec<_>) = (0..3).map(|_| counting_waker()).unzip();
+
+    queue.register(wakers[0].clone());
+    queue.register(wakers[1].clone());
+    assert!(matches!(
+        queue.try_register(wakers[2].clone()),
+        Err(RegisterError::Full(_))
+    ));
+
+    queue.register(wakers[2].clone());
+    assert_eq!(counters[2].woken(), 1);
+
+    assert!(queue.wake_one());
+    queue.register(wakers[2].clone());
+    queue.wake_all();
+
+    assert_eq!(counters[0].woken(), 1);
+    assert_eq!(counters[1].woken(), 1);
+    assert_eq!(counters[2].woken(), 2);
+}
+
+#[test]
+fn overflow_wake_oldest() {
+    let queue = WakerQueue::with_capacity(2, Overflow::WakeOldest);
+    let (wakers, counters): (Vec<_>, Vec<_>) = (0..3).map(|_| counting_waker()).unzip();
+
+    for waker in wakers {
+        queue.register(waker);
+    }
+
+    assert_eq!(counters[0].woken(), 1);
+    assert_eq!(counters[1].woken(), 0);
+    assert_eq!(counters[2].woken(), 0);
+}
+
+#[test]
+fn overflow_wake_new() {
+    let queue = WakerQueue::with_capacity(1, Overflow::WakeNew);
+    let (waker, _count) = counting_waker();
+
+    let first = queue.register_handle(waker.clone());
+    let second = queue.try_register(waker).unwrap();
+
+    assert!(!first.is_notified());
+    assert!(second.is_notified());
+}
+
+#[test]
+fn wait_blocking_timeout_takes_the_waiter_back() {
+    let queue = WakerQueue::new();
+
+    assert!(!queue.wait_blocking_timeout(Duration::from_millis(1)));
+    assert!(!queue.wake_one());
+
+    queue.notify_one();
+    assert!(queue.wait_blocking_timeout(Duration::from_millis(1)));
+
+    queue.close();
+    queue.wait_blocking();
+}
+
+#[test]
+fn park_snoozes_take_time() {
+    let start = Instant::now();
+
+    for step in 0..20 {
+        Park.snooze(step);
+    }
+
+    // steps 10 and up each sleep for the full MAX_TIMEOUT
+    assert!(start.elapsed() >= Park::MAX_TIMEOUT * 10);
+}
+
+struct Reenter(Arc<WakerQueue>);
+
+impl Wake for Reenter {
+    fn wake(self: Arc<Self>) {
+        self.0.wake_one();
+        self.0.register(noop_waker());
+    }
+}
+
+#[test]
+fn waking_from_a_waker() {
+    let queue = Arc::new(WakerQueue::new());
+
+    for _ in 0..10 {
+        queue.register(Waker::from(Arc::new(Reenter(queue.clone()))));
+    }
+
+    queue.wake_n(3);
+    queue.wake_one();
+    queue.wake_all();
+}
+
+#[test]
+fn dropping_frees_pending_wakers() {
+    let queue = WakerQueue::new();
+    let (waker, count) = counting_waker();
+
+    for _ in 0..4 {
+        queue.register(waker.clone());
+    }
+
+    drop(queue);
+
+    assert_eq!(count.woken(), 0);
+    assert_eq!(Arc::strong_count(&count), 2);
+}
+
+#[test]
+fn array_queue() {
+    static QUEUE: ArrayWakerQueue<4> = ArrayWakerQueue::new();
+    let (wakers, counters): (Vec<_>, Vec<_>) = (0..5).map(|_| counting_waker()).unzip();
+
+    for waker in wakers {
+        QUEUE.register(waker);
+    }
+
+    // the fifth didn't fit and was woken right away
+    assert_eq!(counters[4].woken(), 1);
+
+    assert!(QUEUE.wake_one());
+    QUEUE.wake_all();
+    assert!(!QUEUE.wake_one());
+
+    for counter in &counters {
+        assert_eq!(counter.woken(), 1);
+    }
+}
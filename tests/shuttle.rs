@@ -0,0 +1,178 @@
+//! randomized schedules of many concurrent registers and wakes.
+//!
+//! shuttle runs every thread on a single os thread and picks which one runs
+//! at each atomic access, so a schedule only depends on the seed. a failure
+//! prints the schedule, which can be handed to `shuttle::replay`.
+//!
+//! run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg shuttle" cargo test --release --test shuttle
+//! ```
+
+#![cfg(shuttle)]
+
+mod common;
+
+use std::sync::Arc;
+
+use common::counting_waker;
+use shuttle::{
+    future::block_on,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+use wake_queue::WakerQueue;
+
+const SEED: u64 = 0x5eed;
+
+const ITERATIONS: usize = 100;
+
+/// registers `per_thread` wakers from each of `threads` threads while
+/// `wake_all` runs in a loop, then checks every waker was woken exactly once.
+fn register_vs_wake_all(threads: usize, per_thread: usize) {
+    let queue = Arc::new(WakerQueue::new());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let registers: Vec<_> = (0..threads)
+        .map(|_| {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                (0..per_thread)
+                    .map(|_| {
+                        let (waker, counter) = counting_waker();
+                        queue.register(waker);
+                        counter
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let wakers: Vec<_> = (0..2)
+        .map(|_| {
+            let (queue, done) = (queue.clone(), done.clone());
+
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    queue.wake_all();
+                    thread::yield_now();
+                }
+            })
+        })
+        .collect();
+
+    let counters: Vec<_> = registers
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+
+    done.store(true, Ordering::SeqCst);
+
+    for handle in wakers {
+        handle.join().unwrap();
+    }
+
+    // anything the looping wake_alls missed is still queued
+    queue.wake_all();
+
+    for counter in counters {
+        assert_eq!(counter.woken(), 1);
+    }
+}
+
+#[test]
+fn register_vs_wake_all_random() {
+    shuttle::check_random_with_seed(|| register_vs_wake_all(4, 250), SEED, ITERATIONS);
+}
+
+#[test]
+fn register_vs_wake_all_pct() {
+    shuttle::check_pct(|| register_vs_wake_all(4, 50), ITERATIONS, 3);
+}
+
+#[test]
+fn notified_vs_wake_all() {
+    shuttle::check_random_with_seed(
+        || {
+            let queue = Arc::new(WakerQueue::new());
+            let rounds = Arc::new(AtomicUsize::new(0));
+
+            let waiters: Vec<_> = (0..3)
+                .map(|_| {
+                    let (queue, rounds) = (queue.clone(), rounds.clone());
+
+                    // a lost wakeup leaves this blocked forever, which shuttle
+                    // reports as a deadlock
+                    thread::spawn(move || {
+                        block_on(async {
+                            for round in 1..=10 {
+                                loop {
+                                    let notified = queue.notified();
+
+                                    if rounds.load(Ordering::SeqCst) >= round {
+                                        break;
+                                    }
+
+                                    notified.await;
+                                }
+                            }
+                        })
+                    })
+                })
+                .collect();
+
+            for _ in 0..10 {
+                rounds.fetch_add(1, Ordering::SeqCst);
+                queue.wake_all();
+            }
+
+            for handle in waiters {
+                handle.join().unwrap();
+            }
+        },
+        SEED,
+        ITERATIONS,
+    );
+}
+
+#[test]
+fn register_vs_close() {
+    shuttle::check_pct(
+        || {
+            let queue = Arc::new(WakerQueue::new());
+
+            let registers: Vec<_> = (0..3)
+                .map(|_| {
+                    let queue = queue.clone();
+
+                    thread::spawn(move || {
+                        (0..5)
+                            .map(|_| {
+                                let (waker, counter) = counting_waker();
+                                queue.register(waker);
+                                counter
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            queue.close();
+
+            // close can leave the end of its wake to a register that raced with
+            // it, so nothing is checked until every register has returned
+            let counters: Vec<_> = registers
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect();
+
+            for counter in counters {
+                assert_eq!(counter.woken(), 1);
+            }
+        },
+        ITERATIONS * 10,
+        5,
+    );
+}
@@ -0,0 +1,427 @@
+//! multi threaded stress tests.
+//!
+//! besides `cargo test`, these are meant to be run under miri and the
+//! sanitizers, which shrink the iteration counts where they'd take too long:
+//!
+//! ```text
+//! cargo +nightly miri test --test stress
+//! MIRIFLAGS="-Zmiri-tree-borrows" cargo +nightly miri test --test stress
+//! RUSTFLAGS="-Zsanitizer=address" cargo +nightly test --test stress --target x86_64-unknown-linux-gnu
+//! RUSTFLAGS="-Zsanitizer=thread" cargo +nightly test -Zbuild-std --test stress --target x86_64-unknown-linux-gnu
+//! ```
+
+#![cfg(not(any(loom, shuttle)))]
+
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{block_on, counting_waker, poll_once, scaled, timeouts_race_with_wake_one, Counter};
+use wake_queue::{
+    backoff::{Park, Spin, SpinThenYield, Yield},
+    Backoff, Overflow, WakerQueue,
+};
+
+/// registers `per_thread` wakers from each of `threads` threads and returns
+/// their counters once every thread is done.
+fn register_from(
+    queue: &Arc<WakerQueue>,
+    threads: usize,
+    per_thread: usize,
+) -> Vec<thread::JoinHandle<Vec<Arc<Counter>>>> {
+    (0..threads)
+        .map(|_| {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                (0..per_thread)
+                    .map(|_| {
+                        let (waker, counter) = counting_waker();
+                        queue.register(waker);
+                        counter
+                    })
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// registers from a few threads while others wake in a loop, and checks every
+/// waker ends up woken exactly once.
+fn woken_exactly_once(backoff: &'static dyn Backoff, rounds: usize) {
+    for _ in 0..rounds {
+        let queue = Arc::new(WakerQueue::new().with_backoff(backoff));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let registers = register_from(&queue, 4, scaled(1000));
+
+        let wakers: Vec<_> = (0..3)
+            .map(|t| {
+                let (queue, done) = (queue.clone(), done.clone());
+
+                thread::spawn(move || {
+                    while !done.load(Ordering::SeqCst) {
+                        match t {
+                            0 => queue.wake_all(),
+                            1 => drop(queue.wake_one()),
+                            _ => drop(queue.wake_n(7)),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let counters: Vec<_> = registers
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        done.store(true, Ordering::SeqCst);
+
+        for handle in wakers {
+            handle.join().unwrap();
+        }
+
+        queue.wake_all();
+
+        for counter in counters {
+            assert_eq!(counter.woken(), 1);
+        }
+    }
+}
+
+#[test]
+fn every_waker_is_woken_exactly_once() {
+    woken_exactly_once(&SpinThenYield, scaled(20));
+}
+
+#[test]
+fn every_backoff_wakes_exactly_once() {
+    woken_exactly_once(&Spin, scaled(5));
+    woken_exactly_once(&Yield, scaled(5));
+    woken_exactly_once(&Park, scaled(5));
+}
+
+#[test]
+fn cancelled_handles_are_never_woken() {
+    let n = scaled(2000);
+
+    for _ in 0..scaled(20) {
+        let queue = Arc::new(WakerQueue::new());
+        let registered = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(Mutex::new(Vec::new()));
+
+        let registers: Vec<_> = (0..4)
+            .map(|t| {
+                let (queue, registered, done, cancelled) = (
+                    queue.clone(),
+                    registered.clone(),
+                    done.clone(),
+                    cancelled.clone(),
+                );
+
+                thread::spawn(move || {
+                    let mut kept = Vec::new();
+                    let mut counters = Vec::new();
+                    let mut dropped = Vec::new();
+
+                    for i in (t..n).step_by(4) {
+                        let (waker, counter) = counting_waker();
+                        let registration = queue.register_handle(waker);
+
+                        // every third one is cancelled straight away, which
+                        // may or may not beat a concurrent wake
+                        if i % 3 == 0 {
+                            drop(registration);
+                            dropped.push(counter);
+                        } else {
+                            kept.push(registration);
+                            counters.push(counter);
+                        }
+                    }
+
+                    cancelled.lock().unwrap().extend(dropped);
+
+                    // the handles have to outlive the final wake_all
+                    registered.fetch_add(1, Ordering::SeqCst);
+
+                    while !done.load(Ordering::SeqCst) {
+                        thread::yield_now();
+                    }
+
+                    for registration in &kept {
+                        assert!(registration.is_notified());
+                    }
+
+                    counters
+                })
+            })
+            .collect();
+
+        let wakers: Vec<_> = (0..2)
+            .map(|t| {
+                let (queue, registered) = (queue.clone(), registered.clone());
+
+                thread::spawn(move || {
+                    while registered.load(Ordering::SeqCst) < 4 {
+                        if t == 0 {
+                            queue.wake_all();
+                        } else {
+                            queue.wake_n(3);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in wakers {
+            handle.join().unwrap();
+        }
+
+        // a cancel can lose to a wake that already claimed it, but once the
+        // waking threads are gone nothing may reach a cancelled waker again
+        let cancelled = cancelled.lock().unwrap();
+        let before: Vec<_> = cancelled.iter().map(|counter| counter.woken()).collect();
+
+        queue.wake_all();
+        done.store(true, Ordering::SeqCst);
+
+        for (counter, before) in cancelled.iter().zip(before) {
+            assert!(before <= 1);
+            assert_eq!(counter.woken(), before);
+        }
+
+        for handle in registers {
+            for counter in handle.join().unwrap() {
+                assert_eq!(counter.woken(), 1);
+            }
+        }
+    }
+}
+
+#[test]
+fn update_races_with_wake_all() {
+    for _ in 0..scaled(50) {
+        let queue = Arc::new(WakerQueue::new());
+        let (a, a_count) = counting_waker();
+        let (b, b_count) = counting_waker();
+
+        let updater = {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                let mut registration = queue.register_handle(a.clone());
+
+                for i in 0..scaled(1000) {
+                    registration.update(if i % 2 == 0 { &b } else { &a });
+                }
+
+                while !registration.is_notified() {
+                    thread::yield_now();
+                }
+            })
+        };
+
+        while !updater.is_finished() {
+            queue.wake_all();
+            thread::yield_now();
+        }
+
+        updater.join().unwrap();
+
+        // updates after the wake_all wake the new waker right away
+        assert!(a_count.woken() + b_count.woken() >= 1);
+    }
+}
+
+#[test]
+fn notify_one_ping_pong() {
+    let queue = Arc::new(WakerQueue::new());
+    let rounds = scaled(20000);
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let waiter = {
+        let queue = queue.clone();
+
+        thread::spawn(move || {
+            for _ in 0..rounds {
+                block_on(queue.notified());
+                tx.send(()).unwrap();
+            }
+        })
+    };
+
+    for _ in 0..rounds {
+        queue.notify_one();
+        rx.recv().unwrap();
+    }
+
+    waiter.join().unwrap();
+}
+
+#[test]
+fn close_races_with_register() {
+    for _ in 0..scaled(50) {
+        let queue = Arc::new(WakerQueue::new());
+        let registers = register_from(&queue, 3, scaled(500));
+
+        thread::yield_now();
+        queue.close();
+
+        // close can leave the end of its wake to a register that raced with
+        // it, so nothing is checked until every register has returned
+        let counters: Vec<_> = registers
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        for counter in counters {
+            assert_eq!(counter.woken(), 1);
+        }
+
+        block_on(queue.notified());
+    }
+}
+
+#[test]
+fn bounded_queue_under_contention() {
+    let queue = Arc::new(WakerQueue::with_capacity(16, Overflow::WakeOldest));
+    let registers = register_from(&queue, 4, scaled(5000));
+
+    let counters: Vec<_> = registers
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+
+    queue.wake_all();
+
+    for counter in counters {
+        assert_eq!(counter.woken(), 1);
+    }
+}
+
+#[test]
+fn notified_cancelled_while_waking() {
+    for round in 0..scaled(10) {
+        let queue = Arc::new(WakerQueue::new());
+        let stopped = Arc::new(AtomicUsize::new(0));
+
+        let waiters: Vec<_> = (0..4)
+            .map(|t| {
+                let (queue, stopped) = (queue.clone(), stopped.clone());
+
+                thread::spawn(move || {
+                    let (waker, _count) = counting_waker();
+                    let mut held = Vec::new();
+
+                    for i in 0..scaled(3000) {
+                        let mut notified = Box::pin(queue.notified());
+                        poll_once(notified.as_mut(), &waker);
+
+                        // keep a few around so they're dropped out of order
+                        if (i + t + round) % 3 == 0 {
+                            held.push(notified);
+
+                            if held.len() > 5 {
+                                drop(held.swap_remove(i % held.len()));
+                            }
+                        }
+                    }
+
+                    drop(held);
+                    stopped.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        let wakers: Vec<_> = (0..2)
+            .map(|t| {
+                let (queue, stopped) = (queue.clone(), stopped.clone());
+
+                thread::spawn(move || {
+                    while stopped.load(Ordering::SeqCst) < 4 {
+                        if t == 0 {
+                            queue.wake_all();
+                        } else {
+                            queue.wake_n(2);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in waiters.into_iter().chain(wakers) {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn many_notified_waiters() {
+    let queue = Arc::new(WakerQueue::new());
+
+    let waiters: Vec<_> = (0..8)
+        .map(|_| {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                for _ in 0..scaled(2000) {
+                    block_on(queue.notified());
+                }
+            })
+        })
+        .collect();
+
+    while waiters.iter().any(|handle| !handle.is_finished()) {
+        queue.wake_one();
+        queue.wake_all();
+    }
+
+    for handle in waiters {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn blocking_and_async_waiters() {
+    let queue = Arc::new(WakerQueue::new());
+    let rounds = scaled(1000);
+
+    let waiters: Vec<_> = (0..4)
+        .map(|t| {
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                for i in 0..rounds {
+                    match (t + i) % 3 {
+                        0 => queue.wait_blocking(),
+                        1 => while !queue.wait_blocking_timeout(Duration::from_micros(50)) {},
+                        _ => block_on(queue.notified()),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    while waiters.iter().any(|handle| !handle.is_finished()) {
+        queue.wake_one();
+        queue.wake_all();
+    }
+
+    for handle in waiters {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn blocking_timeouts_race_with_wake_one() {
+    timeouts_race_with_wake_one(WakerQueue::wait_blocking_timeout);
+}
@@ -0,0 +1,94 @@
+//! checks of the wakers in `wake_queue::testing`, against a real WakerQueue.
+
+#![cfg(not(any(loom, shuttle)))]
+
+use std::{panic, sync::Arc, thread};
+
+use wake_queue::{
+    testing::{CountingWaker, RecordingWaker, WakeLog},
+    WakerQueue,
+};
+
+#[test]
+fn counting_waker() {
+    let queue = WakerQueue::new();
+    let counter = CountingWaker::new();
+
+    queue.register(counter.waker());
+    counter.assert_not_woken();
+
+    queue.wake_all();
+    counter.assert_woken_once();
+
+    counter.waker().wake_by_ref();
+    counter.clone().assert_woken(2);
+}
+
+#[test]
+fn wake_one_is_fifo() {
+    let queue = WakerQueue::new();
+    let log = WakeLog::new();
+
+    for id in 0..4 {
+        queue.register(log.waker(id));
+    }
+
+    queue.wake_one();
+    queue.wake_n(2);
+    log.assert_fifo(0..3);
+    log.assert_not_woken(3);
+
+    queue.wake_all();
+    log.assert_fifo(0..4);
+}
+
+#[test]
+fn recording_waker_keeps_its_id() {
+    let log = WakeLog::new();
+    let waker = RecordingWaker::new(7, &log);
+
+    assert_eq!(waker.id(), 7);
+
+    waker.waker().wake();
+    waker.waker().wake_by_ref();
+    assert_eq!(log.order(), [7, 7]);
+    assert_eq!(log.count(7), 2);
+
+    log.clear();
+    log.assert_not_woken(7);
+}
+
+#[test]
+fn concurrent_wakeups_are_logged() {
+    let queue = Arc::new(WakerQueue::new());
+    let log = WakeLog::new();
+
+    let registers: Vec<_> = (0..4)
+        .map(|t| {
+            let (queue, log) = (queue.clone(), log.clone());
+            thread::spawn(move || {
+                for id in (t..400).step_by(4) {
+                    queue.register(log.waker(id));
+                }
+            })
+        })
+        .collect();
+
+    for handle in registers {
+        handle.join().unwrap();
+    }
+
+    queue.wake_all();
+    log.assert_each_woken_once(0..400);
+}
+
+#[test]
+fn assertions_panic() {
+    let log = WakeLog::new();
+    log.waker(1).wake();
+    log.waker(0).wake();
+
+    assert!(panic::catch_unwind(|| log.assert_fifo([0, 1])).is_err());
+    assert!(panic::catch_unwind(|| log.assert_woken_once(2)).is_err());
+    assert!(panic::catch_unwind(|| CountingWaker::new().assert_woken_once()).is_err());
+}
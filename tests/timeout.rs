@@ -0,0 +1,60 @@
+//! checks of notified_timeout and notified_until.
+//!
+//! the timer thread behind them is never stopped, so miri has to be told not
+//! to treat it as a leak:
+//!
+//! ```text
+//! MIRIFLAGS="-Zmiri-ignore-leaks" cargo +nightly miri test --test timeout
+//! ```
+
+#![cfg(not(any(loom, shuttle)))]
+
+mod common;
+
+use std::{
+    pin::pin,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{block_on, counting_waker, poll_once};
+use wake_queue::WakerQueue;
+
+#[test]
+fn notified_timeout_takes_the_waiter_back() {
+    let queue = WakerQueue::new();
+
+    let start = Instant::now();
+    assert!(block_on(queue.notified_timeout(Duration::from_millis(5))).is_err());
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert!(!queue.wake_one());
+
+    queue.notify_one();
+    assert_eq!(block_on(queue.notified_until(Instant::now())), Ok(()));
+
+    // woken before the deadline passes
+    let (waker, counter) = counting_waker();
+    let mut notified = pin!(queue.notified_timeout(Duration::from_secs(60)));
+    assert!(!poll_once(notified.as_mut(), &waker));
+    assert!(queue.wake_one());
+    assert_eq!(counter.woken(), 1);
+    assert_eq!(block_on(notified), Ok(()));
+
+    // woken after the deadline passed but before the future noticed
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let mut notified = pin!(queue.notified_until(deadline));
+    assert!(!poll_once(notified.as_mut(), &waker));
+    thread::sleep(deadline - Instant::now());
+    assert!(queue.wake_one());
+    assert_eq!(block_on(notified), Ok(()));
+
+    queue.close();
+    assert_eq!(block_on(queue.notified_timeout(Duration::ZERO)), Ok(()));
+}
+
+#[test]
+fn timeouts_race_with_wake_one() {
+    common::timeouts_race_with_wake_one(|queue, timeout| {
+        block_on(queue.notified_timeout(timeout)).is_ok()
+    });
+}